@@ -0,0 +1,23 @@
+/// Whether an instruction read or wrote memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A range of memory accessed by an instruction, as reported by `Chip8::last_access`.
+///
+/// Instruction fetches are not reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: usize,
+    pub len: usize,
+}
+
+impl Access {
+    /// Whether the access touches any of the `len` bytes from `address`.
+    pub fn overlaps(&self, address: usize, len: usize) -> bool {
+        self.address < address + len && address < self.address + self.len
+    }
+}
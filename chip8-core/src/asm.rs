@@ -0,0 +1,783 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::prelude::v1::*;
+
+use Instruction;
+use Instruction::*;
+
+// Programs are loaded at 0x200, so that is where the first label points.
+const ORIGIN: usize = 0x200;
+const MAX_INCLUDE_DEPTH: usize = 16;
+const MAX_CONSTANT_DEPTH: usize = 64;
+
+/// A problem in assembler source, pointing at the file, line and column it was found at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.file, self.line, self.column, self.message
+            )
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assembles source text into a program to be loaded at 0x200.
+///
+/// The syntax is the one produced by the disassembler, one instruction per line, plus:
+///
+/// - `; comments` until the end of the line
+/// - `label:` definitions, optionally followed by an instruction on the same line
+/// - `NAME EQU expression` constants
+/// - `DB` and `DW` directives with comma separated byte and word data
+/// - `SPRITE` rows drawn with `#` (or `X`, `1`) and `.` (or `0`, `_`), up to 16 pixels wide
+/// - `INCLUDE "file"` to assemble another file in place, relative to the including file
+///
+/// Numbers are decimal, `0x` hexadecimal or `0b`/`%` binary; expressions add and subtract
+/// numbers, labels and constants. Mnemonics and register names are case-insensitive.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::new();
+    assembler.parse("<source>", source, Path::new("."), 0)?;
+    assembler.finish()
+}
+
+/// Assembles a source file, resolving `INCLUDE`s relative to its directory.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AsmError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        column: 0,
+        message: err.to_string(),
+    })?;
+    let mut assembler = Assembler::new();
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    assembler.parse(&path.display().to_string(), &source, dir, 0)?;
+    assembler.finish()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Pos {
+    file: usize,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Operand {
+    pos: Pos,
+    text: String,
+}
+
+enum Item {
+    Code {
+        pos: Pos,
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Bytes(Vec<Operand>),
+    Words(Vec<Operand>),
+    Raw(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Arg {
+    V(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(i64),
+    Value(i64),
+}
+
+struct Assembler {
+    files: Vec<String>,
+    items: Vec<Item>,
+    address: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, Operand>,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Assembler {
+            files: vec![],
+            items: vec![],
+            address: ORIGIN,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+        }
+    }
+
+    fn error<S: Into<String>>(&self, pos: Pos, message: S) -> AsmError {
+        AsmError {
+            file: self.files[pos.file].clone(),
+            line: pos.line,
+            column: pos.column,
+            message: message.into(),
+        }
+    }
+
+    // First pass: split lines into items and assign addresses to labels.
+    fn parse(
+        &mut self,
+        name: &str,
+        source: &str,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        self.files.push(name.to_string());
+        let file = self.files.len() - 1;
+        for (index, line) in source.lines().enumerate() {
+            let pos = Pos {
+                file,
+                line: index + 1,
+                column: 1,
+            };
+            self.parse_line(pos, line, dir, depth)?;
+        }
+        Ok(())
+    }
+
+    fn parse_line(
+        &mut self,
+        pos: Pos,
+        line: &str,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let code = strip_comment(line);
+        let (column, word, rest) = match next_word(code, 0) {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+        let at = |column: usize| Pos {
+            column: column + 1,
+            ..pos
+        };
+
+        let (column, word, rest) = if let Some(name) = word.strip_suffix(':') {
+            self.define(at(column), name)?;
+            self.labels.insert(name.to_string(), self.address);
+            match next_word(code, rest) {
+                Some(found) => found,
+                None => return Ok(()),
+            }
+        } else {
+            (column, word, rest)
+        };
+
+        if let Some((equ_column, equ, value)) = next_word(code, rest) {
+            if equ.eq_ignore_ascii_case("EQU") {
+                self.define(at(column), word)?;
+                let operands = split_operands(code, value, pos);
+                if operands.len() != 1 {
+                    return Err(self.error(at(equ_column), "EQU needs exactly one value"));
+                }
+                self.constants
+                    .insert(word.to_string(), operands.into_iter().next().unwrap());
+                return Ok(());
+            }
+        }
+
+        let mnemonic = word.to_uppercase();
+        let operands = split_operands(code, rest, pos);
+        match mnemonic.as_str() {
+            "DB" => {
+                self.address += operands.len();
+                self.items.push(Item::Bytes(operands));
+            }
+            "DW" => {
+                self.address += 2 * operands.len();
+                self.items.push(Item::Words(operands));
+            }
+            "SPRITE" => {
+                let bytes = self.sprite(at(column), &operands)?;
+                self.address += bytes.len();
+                self.items.push(Item::Raw(bytes));
+            }
+            "INCLUDE" => {
+                let operand = match operands.first() {
+                    Some(operand) if operands.len() == 1 => operand,
+                    _ => return Err(self.error(at(column), "INCLUDE needs one file name")),
+                };
+                let text = operand.text.as_str();
+                if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+                    return Err(self.error(operand.pos, "file name must be quoted"));
+                }
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(self.error(operand.pos, "includes are nested too deeply"));
+                }
+                let path = dir.join(&text[1..text.len() - 1]);
+                let source = fs::read_to_string(&path).map_err(|err| {
+                    self.error(
+                        operand.pos,
+                        format!("unable to include {}: {}", path.display(), err),
+                    )
+                })?;
+                let include_dir = path.parent().unwrap_or(dir).to_path_buf();
+                self.parse(
+                    &path.display().to_string(),
+                    &source,
+                    &include_dir,
+                    depth + 1,
+                )?;
+            }
+            _ => {
+                let long = mnemonic == "LD"
+                    && operands.len() == 2
+                    && operands[1].text.to_uppercase().starts_with("LONG ");
+                self.address += if long { 4 } else { 2 };
+                self.items.push(Item::Code {
+                    pos: at(column),
+                    mnemonic,
+                    operands,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&self, pos: Pos, name: &str) -> Result<(), AsmError> {
+        let valid = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid || is_reserved(name) {
+            return Err(self.error(pos, format!("invalid symbol name '{}'", name)));
+        }
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return Err(self.error(pos, format!("symbol '{}' is already defined", name)));
+        }
+        Ok(())
+    }
+
+    fn sprite(&self, pos: Pos, operands: &[Operand]) -> Result<Vec<u8>, AsmError> {
+        let mut rows = vec![];
+        for operand in operands {
+            let mut offset = 0;
+            while let Some((column, row, rest)) = next_word(&operand.text, offset) {
+                rows.push((
+                    Pos {
+                        column: operand.pos.column + column,
+                        ..operand.pos
+                    },
+                    row,
+                ));
+                offset = rest;
+            }
+        }
+        if rows.is_empty() {
+            return Err(self.error(pos, "SPRITE needs at least one row"));
+        }
+        let wide = rows.iter().any(|&(_, row)| row.len() > 8);
+        let mut bytes = vec![];
+        for (pos, row) in rows {
+            if row.len() > 16 {
+                return Err(self.error(pos, "sprite rows are at most 16 pixels wide"));
+            }
+            let mut bits: u16 = 0;
+            for (i, c) in row.chars().enumerate() {
+                match c {
+                    '#' | 'X' | 'x' | '1' => bits |= 0x8000 >> i,
+                    '.' | '0' | '_' => (),
+                    _ => return Err(self.error(pos, format!("invalid sprite pixel '{}'", c))),
+                }
+            }
+            bytes.push((bits >> 8) as u8);
+            if wide {
+                bytes.push(bits as u8);
+            }
+        }
+        Ok(bytes)
+    }
+
+    // Second pass: evaluate operands and encode every item.
+    fn finish(self) -> Result<Vec<u8>, AsmError> {
+        let mut program = vec![];
+        for item in &self.items {
+            match *item {
+                Item::Code {
+                    pos,
+                    ref mnemonic,
+                    ref operands,
+                } => {
+                    let instruction = self.encode(pos, mnemonic, operands)?;
+                    program.extend_from_slice(&instruction.to_bytes());
+                }
+                Item::Bytes(ref operands) => {
+                    for operand in operands {
+                        let value = self.evaluate(operand, 0)?;
+                        program.push(self.byte(operand.pos, value)?);
+                    }
+                }
+                Item::Words(ref operands) => {
+                    for operand in operands {
+                        let value = self.evaluate(operand, 0)?;
+                        if !(-0x8000..=0xFFFF).contains(&value) {
+                            return Err(self.error(operand.pos, "value does not fit in a word"));
+                        }
+                        program.push((value >> 8) as u8);
+                        program.push(value as u8);
+                    }
+                }
+                Item::Raw(ref bytes) => program.extend_from_slice(bytes),
+            }
+        }
+        Ok(program)
+    }
+
+    fn encode(
+        &self,
+        pos: Pos,
+        mnemonic: &str,
+        operands: &[Operand],
+    ) -> Result<Instruction, AsmError> {
+        let mut args = vec![];
+        for operand in operands {
+            args.push(self.arg(operand)?);
+        }
+        let at = |i: usize| operands[i].pos;
+
+        use self::Arg::*;
+        let instruction = match (mnemonic, &args[..]) {
+            ("CLS", &[]) => Clear,
+            ("RET", &[]) => Return,
+            ("SCD", &[Value(n)]) => ScrollDown(self.nibble(at(0), n)?),
+            ("SCU", &[Value(n)]) => ScrollUp(self.nibble(at(0), n)?),
+            ("SCR", &[]) => ScrollRight,
+            ("SCL", &[]) => ScrollLeft,
+            ("EXIT", &[]) => Exit,
+            ("LOW", &[]) => LowRes,
+            ("HIGH", &[]) => HighRes,
+            ("JP", &[Value(a)]) => Jump(self.address(at(0), a)?),
+            ("JP", &[V(0), Value(a)]) => JumpOffset(self.address(at(1), a)?),
+            ("CALL", &[Value(a)]) => Call(self.address(at(0), a)?),
+            ("SE", &[V(x), Value(kk)]) => SkipEqualByte(x, self.byte(at(1), kk)?),
+            ("SE", &[V(x), V(y)]) => SkipEqual(x, y),
+            ("SNE", &[V(x), Value(kk)]) => SkipNotEqualByte(x, self.byte(at(1), kk)?),
+            ("SNE", &[V(x), V(y)]) => SkipNotEqual(x, y),
+            ("SAVE", &[V(x), V(y)]) => SaveRange(x, y),
+            ("LOAD", &[V(x), V(y)]) => LoadRange(x, y),
+            ("LD", &[V(x), Value(kk)]) => LoadByte(x, self.byte(at(1), kk)?),
+            ("LD", &[V(x), V(y)]) => Load(x, y),
+            ("LD", &[I, Value(a)]) => LoadI(self.address(at(1), a)?),
+            ("LD", &[I, Long(a)]) => LoadILong(self.word(at(1), a)?),
+            ("LD", &[V(x), Dt]) => LoadDelay(x),
+            ("LD", &[V(x), K]) => WaitKey(x),
+            ("LD", &[Dt, V(x)]) => SetDelay(x),
+            ("LD", &[St, V(x)]) => SetSound(x),
+            ("LD", &[F, V(x)]) => Font(x),
+            ("LD", &[Hf, V(x)]) => BigFont(x),
+            ("LD", &[B, V(x)]) => Bcd(x),
+            ("LD", &[IndirectI, V(x)]) => Store(x),
+            ("LD", &[V(x), IndirectI]) => Restore(x),
+            ("LD", &[R, V(x)]) => StoreFlags(x),
+            ("LD", &[V(x), R]) => RestoreFlags(x),
+            ("ADD", &[V(x), Value(kk)]) => AddByte(x, self.byte(at(1), kk)?),
+            ("ADD", &[V(x), V(y)]) => Add(x, y),
+            ("ADD", &[I, V(x)]) => AddI(x),
+            ("OR", &[V(x), V(y)]) => Or(x, y),
+            ("AND", &[V(x), V(y)]) => And(x, y),
+            ("XOR", &[V(x), V(y)]) => Xor(x, y),
+            ("SUB", &[V(x), V(y)]) => Sub(x, y),
+            ("SHR", &[V(x)]) => ShiftRight(x, x),
+            ("SHR", &[V(x), V(y)]) => ShiftRight(x, y),
+            ("SUBN", &[V(x), V(y)]) => SubReverse(x, y),
+            ("SHL", &[V(x)]) => ShiftLeft(x, x),
+            ("SHL", &[V(x), V(y)]) => ShiftLeft(x, y),
+            ("RND", &[V(x), Value(kk)]) => Random(x, self.byte(at(1), kk)?),
+            ("DRW", &[V(x), V(y), Value(n)]) => Draw(x, y, self.nibble(at(2), n)?),
+            ("SKP", &[V(x)]) => SkipKey(x),
+            ("SKNP", &[V(x)]) => SkipNotKey(x),
+            ("PLANE", &[Value(n)]) => Plane(self.nibble(at(0), n)?),
+            ("AUDIO", &[]) => Audio,
+            ("PITCH", &[V(x)]) => Pitch(x),
+            _ if is_mnemonic(mnemonic) => {
+                return Err(self.error(pos, format!("invalid operands for {}", mnemonic)));
+            }
+            _ => return Err(self.error(pos, format!("unknown instruction {}", mnemonic))),
+        };
+        Ok(instruction)
+    }
+
+    fn arg(&self, operand: &Operand) -> Result<Arg, AsmError> {
+        let upper = operand.text.to_uppercase();
+        let arg = match upper.as_str() {
+            "I" => Arg::I,
+            "[I]" => Arg::IndirectI,
+            "DT" => Arg::Dt,
+            "ST" => Arg::St,
+            "K" => Arg::K,
+            "F" => Arg::F,
+            "HF" => Arg::Hf,
+            "B" => Arg::B,
+            "R" => Arg::R,
+            _ => {
+                if let Some(register) = register(&upper) {
+                    Arg::V(register)
+                } else if upper.starts_with("LONG ") {
+                    let value = Operand {
+                        pos: Pos {
+                            column: operand.pos.column + 5,
+                            ..operand.pos
+                        },
+                        text: operand.text[5..].trim_start().to_string(),
+                    };
+                    Arg::Long(self.evaluate(&value, 0)?)
+                } else {
+                    Arg::Value(self.evaluate(operand, 0)?)
+                }
+            }
+        };
+        Ok(arg)
+    }
+
+    fn evaluate(&self, operand: &Operand, depth: usize) -> Result<i64, AsmError> {
+        let text = operand.text.as_str();
+        if text.is_empty() {
+            return Err(self.error(operand.pos, "missing value"));
+        }
+        let mut total: i64 = 0;
+        let mut sign = 1;
+        let mut expect_term = true;
+        let mut chars = text.char_indices().peekable();
+        while let Some(&(start, c)) = chars.peek() {
+            let pos = Pos {
+                column: operand.pos.column + start,
+                ..operand.pos
+            };
+            if c.is_whitespace() {
+                chars.next();
+            } else if (c == '+' || c == '-') && expect_term {
+                if c == '-' {
+                    sign = -sign;
+                }
+                chars.next();
+            } else if c == '+' || c == '-' {
+                sign = if c == '-' { -1 } else { 1 };
+                expect_term = true;
+                chars.next();
+            } else if expect_term {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '%' {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                if end == start {
+                    return Err(self.error(pos, format!("unexpected '{}'", c)));
+                }
+                total += sign * self.term(pos, &text[start..end], depth)?;
+                sign = 1;
+                expect_term = false;
+            } else {
+                return Err(self.error(pos, format!("unexpected '{}'", c)));
+            }
+        }
+        if expect_term {
+            return Err(self.error(operand.pos, "expression ends with an operator"));
+        }
+        Ok(total)
+    }
+
+    fn term(&self, pos: Pos, term: &str, depth: usize) -> Result<i64, AsmError> {
+        let lower = term.to_lowercase();
+        let number = if let Some(hex) = lower.strip_prefix("0x") {
+            Some(i64::from_str_radix(hex, 16))
+        } else if let Some(binary) = lower.strip_prefix("0b").or_else(|| lower.strip_prefix('%')) {
+            Some(i64::from_str_radix(binary, 2))
+        } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+            Some(lower.parse::<i64>())
+        } else {
+            None
+        };
+        if let Some(number) = number {
+            return number.map_err(|_| self.error(pos, format!("invalid number '{}'", term)));
+        }
+
+        if let Some(&address) = self.labels.get(term) {
+            return Ok(address as i64);
+        }
+        if let Some(value) = self.constants.get(term) {
+            if depth >= MAX_CONSTANT_DEPTH {
+                return Err(self.error(pos, format!("constant '{}' refers to itself", term)));
+            }
+            return self.evaluate(value, depth + 1);
+        }
+        Err(self.error(pos, format!("undefined symbol '{}'", term)))
+    }
+
+    fn nibble(&self, pos: Pos, value: i64) -> Result<u8, AsmError> {
+        if !(0..=0xF).contains(&value) {
+            return Err(self.error(pos, "value does not fit in 4 bits"));
+        }
+        Ok(value as u8)
+    }
+
+    // Negative bytes are accepted as two's complement, e.g. `ADD V0, -1`.
+    fn byte(&self, pos: Pos, value: i64) -> Result<u8, AsmError> {
+        if !(-0x80..=0xFF).contains(&value) {
+            return Err(self.error(pos, "value does not fit in a byte"));
+        }
+        Ok(value as u8)
+    }
+
+    fn address(&self, pos: Pos, value: i64) -> Result<u16, AsmError> {
+        if !(0..=0xFFF).contains(&value) {
+            return Err(self.error(pos, "address does not fit in 12 bits"));
+        }
+        Ok(value as u16)
+    }
+
+    fn word(&self, pos: Pos, value: i64) -> Result<u16, AsmError> {
+        if !(0..=0xFFFF).contains(&value) {
+            return Err(self.error(pos, "address does not fit in 16 bits"));
+        }
+        Ok(value as u16)
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+// Finds the next whitespace separated word at or after `offset`, returning its start, the word
+// and the offset just past it.
+fn next_word(text: &str, offset: usize) -> Option<(usize, &str, usize)> {
+    let rest = &text[offset..];
+    let start = offset + rest.find(|c: char| !c.is_whitespace())?;
+    let end = text[start..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |i| start + i);
+    Some((start, &text[start..end], end))
+}
+
+fn split_operands(code: &str, offset: usize, pos: Pos) -> Vec<Operand> {
+    let mut operands = vec![];
+    if code[offset..].trim().is_empty() {
+        return operands;
+    }
+    let mut start = offset;
+    let mut quoted = false;
+    for (i, c) in code[offset..].char_indices() {
+        let i = offset + i;
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == ',' && !quoted {
+            operands.push(operand(code, start, i, pos));
+            start = i + 1;
+        }
+    }
+    operands.push(operand(code, start, code.len(), pos));
+    operands
+}
+
+fn operand(code: &str, start: usize, end: usize, pos: Pos) -> Operand {
+    let raw = &code[start..end];
+    let leading = raw.len() - raw.trim_start().len();
+    Operand {
+        pos: Pos {
+            column: start + leading + 1,
+            ..pos
+        },
+        text: raw.trim().to_string(),
+    }
+}
+
+fn register(name: &str) -> Option<u8> {
+    if name.len() == 2 && name.starts_with('V') {
+        u8::from_str_radix(&name[1..], 16).ok()
+    } else {
+        None
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    register(&upper).is_some()
+        || is_mnemonic(&upper)
+        || ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "EQU"].contains(&upper.as_str())
+}
+
+fn is_mnemonic(name: &str) -> bool {
+    [
+        "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+        "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW",
+        "SKP", "SKNP", "PLANE", "AUDIO", "PITCH", "DB", "DW", "SPRITE", "INCLUDE",
+    ]
+    .contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disasm;
+
+    #[test]
+    fn instructions() {
+        let program = assemble(
+            "
+            CLS
+            ld v3, 0x42
+            DRW V4, V5, 5
+            LD I, LONG 0xBEEF
+            ADD V0, -1
+            SHR VA
+            ",
+        )
+        .unwrap();
+        assert!(
+            program
+                == vec![
+                    0x00, 0xE0, 0x63, 0x42, 0xD4, 0x55, 0xF0, 0x00, 0xBE, 0xEF, 0x70, 0xFF, 0x8A,
+                    0xA6,
+                ]
+        );
+    }
+
+    #[test]
+    fn labels_and_constants() {
+        let program = assemble(
+            "
+            SPEED EQU 2 + OFFSET
+            OFFSET EQU 1
+            start:  LD V0, SPEED   ; forward references are allowed
+            loop:   JP end
+                    JP start
+            end:    LD I, data + 1
+            data:   DB 1, 0b101, %11, 0xFF
+                    DW loop
+            ",
+        )
+        .unwrap();
+        assert!(
+            program
+                == vec![
+                    0x60, 0x03, 0x12, 0x06, 0x12, 0x00, 0xA2, 0x09, 0x01, 0x05, 0x03, 0xFF, 0x02,
+                    0x02,
+                ]
+        );
+    }
+
+    #[test]
+    fn sprites() {
+        let program = assemble(
+            "
+            SPRITE ####.... #..#....
+            SPRITE ################, #..............#
+            ",
+        )
+        .unwrap();
+        assert!(program == vec![0xF0, 0x90, 0xFF, 0xFF, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| assemble(source).unwrap_err();
+
+        let err = error("  CLS\n  FOO V1");
+        assert!((err.line, err.column) == (2, 3));
+        assert!(err.message == "unknown instruction FOO");
+
+        let err = error("LD V1,  0x100");
+        assert!((err.line, err.column) == (1, 9));
+        assert!(err.message == "value does not fit in a byte");
+
+        let err = error("JP nowhere");
+        assert!((err.line, err.column) == (1, 4));
+        assert!(err.to_string() == "<source>:1:4: undefined symbol 'nowhere'");
+
+        let err = error("a:\na:");
+        assert!(err.message == "symbol 'a' is already defined");
+        assert!(error("LD V1, V2, V3").message == "invalid operands for LD");
+        assert!(error("X EQU X\nLD V0, X").message == "constant 'X' refers to itself");
+        assert!(error("INCLUDE \"missing.asm\"").line == 1);
+    }
+
+    #[test]
+    fn include() {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("main.asm"),
+            "
+            start:  CALL draw
+                    JP start
+                    INCLUDE \"lib/draw.asm\"
+                    DB SIZE
+            ",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("lib/draw.asm"),
+            "
+            SIZE EQU 3
+            draw:   LD I, shape
+                    RET
+            shape:  DB 0xFF
+                    JP start
+            ",
+        )
+        .unwrap();
+        let program = assemble_file(dir.join("main.asm"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            program.unwrap()
+                == vec![0x22, 0x04, 0x12, 0x00, 0xA2, 0x08, 0x00, 0xEE, 0xFF, 0x12, 0x00, 0x03]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for opcode in 0..=0xFFFFu32 {
+            let bytes = [(opcode >> 8) as u8, opcode as u8, 0x12, 0x34];
+            let line = disasm::disassemble(&bytes, 0x200);
+            assert!(assemble(&line.text).unwrap() == line.bytes);
+        }
+    }
+
+    #[test]
+    fn round_trip_program() {
+        let program = include_bytes!("../../data/10print.ch8");
+        let source: Vec<String> = disasm::listing(program, 0x200)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert!(assemble(&source.join("\n")).unwrap() == program[..]);
+    }
+}
@@ -0,0 +1,141 @@
+use std::prelude::v1::*;
+
+use {Bus, Chip8, Display};
+
+/// Receives the samples generated by `Audio`, e.g. to play them or to capture them.
+pub trait AudioSink {
+    /// Consumes mono samples in the range -1.0 to 1.0.
+    fn write(&mut self, samples: &[f32]);
+}
+
+impl AudioSink for Vec<f32> {
+    fn write(&mut self, samples: &[f32]) {
+        self.extend_from_slice(samples);
+    }
+}
+
+/// Generates the tone that plays while the sound timer is active.
+///
+/// Plain CHIP-8 programs get a square wave. XO-CHIP programs that load an audio pattern with
+/// 0xF002 get that 1-bit pattern played back at the rate selected with 0xFX3A.
+pub struct Audio {
+    pub sample_rate: u32,
+    pub frequency: f32,
+    pub volume: f32,
+    phase: f32,
+    pending: f32,
+}
+
+impl Audio {
+    pub fn new(sample_rate: u32) -> Self {
+        Audio {
+            sample_rate,
+            frequency: 440.0,
+            volume: 0.25,
+            phase: 0.0,
+            pending: 0.0,
+        }
+    }
+
+    /// Generates one 1/60 second frame of samples for the current state of `chip8`.
+    pub fn render_frame<D: Display, B: Bus, S: AudioSink>(
+        &mut self,
+        chip8: &Chip8<D, B>,
+        sink: &mut S,
+    ) {
+        // Carry the fractional sample over so that sample rates not divisible by 60 keep time.
+        self.pending += self.sample_rate as f32 / 60.0;
+        let count = self.pending as usize;
+        self.pending -= count as f32;
+
+        let mut samples = vec![0.0; count];
+        if chip8.sound_timer > 0 {
+            match chip8.audio_pattern {
+                Some(pattern) => {
+                    let rate = 4000.0 * 2f32.powf((chip8.pitch as f32 - 64.0) / 48.0);
+                    let step = rate / self.sample_rate as f32;
+                    for sample in samples.iter_mut() {
+                        let bit = self.phase as usize % 128;
+                        let on = (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+                        *sample = if on { self.volume } else { -self.volume };
+                        self.phase = (self.phase + step) % 128.0;
+                    }
+                }
+                None => {
+                    let step = self.frequency / self.sample_rate as f32;
+                    for sample in samples.iter_mut() {
+                        *sample = if self.phase < 0.5 {
+                            self.volume
+                        } else {
+                            -self.volume
+                        };
+                        self.phase = (self.phase + step) % 1.0;
+                    }
+                }
+            }
+        } else {
+            self.phase = 0.0;
+        }
+        sink.write(&samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silent_without_sound_timer() {
+        let chip8 = Chip8::new();
+        let mut audio = Audio::new(44100);
+        let mut samples = vec![];
+        audio.render_frame(&chip8, &mut samples);
+        assert!(samples.len() == 735);
+        assert!(samples.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn square_wave() {
+        let mut chip8 = Chip8::new();
+        chip8.sound_timer = 60;
+        let mut audio = Audio::new(48000);
+        let mut samples = vec![];
+        for _ in 0..60 {
+            audio.render_frame(&chip8, &mut samples);
+        }
+        assert!(samples.len() == 48000);
+        let rising = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] > 0.0)
+            .count();
+        assert!((439..=440).contains(&rising));
+    }
+
+    #[test]
+    fn frame_lengths_keep_time() {
+        let chip8 = Chip8::new();
+        let mut audio = Audio::new(22050);
+        let mut samples = vec![];
+        for _ in 0..60 {
+            audio.render_frame(&chip8, &mut samples);
+        }
+        assert!(samples.len() == 22050);
+    }
+
+    #[test]
+    fn audio_pattern() {
+        let mut chip8 = Chip8::new();
+        chip8.sound_timer = 1;
+        let mut pattern = [0; 16];
+        pattern[0] = 0xF0;
+        chip8.audio_pattern = Some(pattern);
+        // At pitch 64 the pattern plays at 4000 bits per second, one bit per sample.
+        let mut audio = Audio::new(4000);
+        let mut samples = vec![];
+        audio.render_frame(&chip8, &mut samples);
+        audio.render_frame(&chip8, &mut samples);
+        assert!(samples[..4].iter().all(|&s| s > 0.0));
+        assert!(samples[4..128].iter().all(|&s| s < 0.0));
+        assert!(samples[128..132].iter().all(|&s| s > 0.0));
+    }
+}
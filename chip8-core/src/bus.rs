@@ -0,0 +1,190 @@
+use {AccessKind, BIG_FONTS, BIG_FONTS_START, FONTS};
+
+/// The size of the address space, which XO-CHIP extends to 64KiB.
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// The memory the machine runs from.
+///
+/// `peek` and `poke` access memory directly, for loading programs, save states and tools
+/// such as debuggers. Instructions reading and writing data go through `read` and `write`
+/// instead, which can be overridden to observe those accesses, and may only write where
+/// `writable` allows. Addresses are always below `MEMORY_SIZE`.
+pub trait Bus {
+    fn peek(&self, address: usize) -> u8;
+
+    fn poke(&mut self, address: usize, value: u8);
+
+    /// Whether instructions may write to the address.
+    fn writable(&self, _address: usize) -> bool {
+        true
+    }
+
+    /// Reads a byte of data for an instruction.
+    fn read(&mut self, address: usize) -> u8 {
+        self.peek(address)
+    }
+
+    /// Writes a byte of data for an instruction, once `writable` has allowed it.
+    fn write(&mut self, address: usize, value: u8) {
+        self.poke(address, value)
+    }
+}
+
+/// The default bus: flat RAM over the whole address space, with optional read-only regions.
+pub struct Ram {
+    pub bytes: [u8; MEMORY_SIZE],
+    read_only: [u64; MEMORY_SIZE / 64],
+}
+
+impl Ram {
+    /// Zeroed memory that is writable everywhere.
+    pub fn new() -> Self {
+        Ram {
+            bytes: [0; MEMORY_SIZE],
+            read_only: [0; MEMORY_SIZE / 64],
+        }
+    }
+
+    /// Makes `len` bytes from `address` read-only, so instructions writing there fail.
+    pub fn protect(&mut self, address: usize, len: usize) {
+        for address in address..(address + len).min(MEMORY_SIZE) {
+            self.read_only[address / 64] |= 1 << (address % 64);
+        }
+    }
+
+    /// Makes the built-in small and large fonts read-only.
+    pub fn protect_fonts(&mut self) {
+        self.protect(0, FONTS.len());
+        self.protect(BIG_FONTS_START, BIG_FONTS.len());
+    }
+
+    /// Makes 0x000-0x1FF read-only: the fonts and, on the original machines, the interpreter.
+    pub fn protect_interpreter(&mut self) {
+        self.protect(0, 0x200);
+    }
+
+    /// Makes all memory writable again.
+    pub fn unprotect(&mut self) {
+        self.read_only = [0; MEMORY_SIZE / 64];
+    }
+}
+
+impl Default for Ram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for Ram {
+    fn peek(&self, address: usize) -> u8 {
+        self.bytes[address]
+    }
+
+    fn poke(&mut self, address: usize, value: u8) {
+        self.bytes[address] = value;
+    }
+
+    fn writable(&self, address: usize) -> bool {
+        self.read_only[address / 64] & (1 << (address % 64)) == 0
+    }
+}
+
+/// Wraps a bus to call a hook on every data access an instruction makes, with the address
+/// and the byte read or written.
+pub struct Hooked<B, F> {
+    pub bus: B,
+    pub hook: F,
+}
+
+impl<B: Bus, F: FnMut(AccessKind, usize, u8)> Bus for Hooked<B, F> {
+    fn peek(&self, address: usize) -> u8 {
+        self.bus.peek(address)
+    }
+
+    fn poke(&mut self, address: usize, value: u8) {
+        self.bus.poke(address, value)
+    }
+
+    fn writable(&self, address: usize) -> bool {
+        self.bus.writable(address)
+    }
+
+    fn read(&mut self, address: usize) -> u8 {
+        let value = self.bus.read(address);
+        (self.hook)(AccessKind::Read, address, value);
+        value
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        self.bus.write(address, value);
+        (self.hook)(AccessKind::Write, address, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Chip8, Chip8Error};
+    #[cfg(feature = "alloc")]
+    use {Framebuffer, Quirks};
+
+    #[test]
+    fn read_only_regions() {
+        let mut ram = Ram::new();
+        ram.protect(0x300, 3);
+        assert!(ram.writable(0x2FF) && !ram.writable(0x300) && !ram.writable(0x302));
+        assert!(ram.writable(0x303));
+        ram.protect_fonts();
+        assert!(!ram.writable(0) && !ram.writable(BIG_FONTS_START + BIG_FONTS.len() - 1));
+        assert!(ram.writable(BIG_FONTS_START + BIG_FONTS.len()));
+        ram.unprotect();
+        ram.protect_interpreter();
+        assert!(!ram.writable(0x1FF) && ram.writable(0x200) && ram.writable(0x300));
+
+        // Writing to a protected region stops the instruction before it changes anything.
+        let mut chip8 = Chip8::new();
+        chip8.bus.protect_fonts();
+        chip8.load(&[0x60, 0xFF, 0xA0, 0x4E, 0xF1, 0x55]).unwrap();
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        let err = chip8.cycle().unwrap_err();
+        assert!(
+            err == Chip8Error::ReadOnly {
+                pc: 0x204,
+                opcode: 0xF155,
+                address: 0x4E
+            }
+        );
+        assert!(chip8.bus.bytes[0x4E..0x50] == [0x80, 0x80]);
+        assert!(chip8.pc == 0x204);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn hooks() {
+        let mut accesses = vec![];
+        {
+            let bus = Hooked {
+                bus: Ram::new(),
+                hook: |kind, address, value| accesses.push((kind, address, value)),
+            };
+            let mut chip8 = Chip8::with_display_and_bus(Quirks::default(), Framebuffer::new(), bus);
+            // Store V0 = 7 at 0x300, then load it back into V0 and V1.
+            chip8
+                .load(&[0x60, 0x07, 0xA3, 0x00, 0xF0, 0x55, 0xA3, 0x00, 0xF1, 0x65])
+                .unwrap();
+            for _ in 0..5 {
+                chip8.cycle().unwrap();
+            }
+            assert!(chip8.registers[1] == 0);
+        }
+        assert!(
+            accesses
+                == [
+                    (AccessKind::Write, 0x300, 7),
+                    (AccessKind::Read, 0x300, 7),
+                    (AccessKind::Read, 0x301, 0),
+                ]
+        );
+    }
+}
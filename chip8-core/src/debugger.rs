@@ -0,0 +1,611 @@
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use disasm;
+use {AccessKind, Bus, Chip8, Chip8Error, Display, Framebuffer, Ram, MEMORY_SIZE};
+
+/// Why the debugger stopped execution.
+///
+/// Breakpoints stop before the instruction at their address; watchpoints stop after the
+/// instruction at `pc` triggered them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(usize),
+    Read {
+        pc: usize,
+        address: usize,
+    },
+    Write {
+        pc: usize,
+        address: usize,
+    },
+    Register {
+        pc: usize,
+        register: u8,
+        old: u8,
+        new: u8,
+    },
+    Index {
+        pc: usize,
+        old: usize,
+        new: usize,
+    },
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stop::Breakpoint(address) => write!(f, "Breakpoint at 0x{:03X}", address),
+            Stop::Read { pc, address } => {
+                write!(f, "Watchpoint: 0x{:03X} read 0x{:03X}", pc, address)
+            }
+            Stop::Write { pc, address } => {
+                write!(f, "Watchpoint: 0x{:03X} wrote 0x{:03X}", pc, address)
+            }
+            Stop::Register {
+                pc,
+                register,
+                old,
+                new,
+            } => write!(
+                f,
+                "Watchpoint: 0x{:03X} changed V{:X} from 0x{:02X} to 0x{:02X}",
+                pc, register, old, new
+            ),
+            Stop::Index { pc, old, new } => write!(
+                f,
+                "Watchpoint: 0x{:03X} changed I from 0x{:03X} to 0x{:03X}",
+                pc, old, new
+            ),
+        }
+    }
+}
+
+/// A condition that stops execution after the instruction that meets it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// An instruction reads any of the given number of bytes from an address (FX65, DXYN...).
+    Read(usize, usize),
+    /// An instruction writes any of the given number of bytes from an address (FX33, FX55...).
+    Write(usize, usize),
+    /// An instruction changes a V register.
+    Register(u8),
+    /// An instruction changes I.
+    Index,
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Watchpoint::Read(address, len) => write!(f, "read 0x{:03X} {}", address, len),
+            Watchpoint::Write(address, len) => write!(f, "write 0x{:03X} {}", address, len),
+            Watchpoint::Register(x) => write!(f, "V{:X}", x),
+            Watchpoint::Index => write!(f, "I"),
+        }
+    }
+}
+
+/// A debugger command, as typed at the prompt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// `break [ADDRESS]`: Set a breakpoint, or list them without an address.
+    Break(Option<usize>),
+    /// `delete ADDRESS`: Clear a breakpoint.
+    Delete(usize),
+    /// `watch [read|write ADDRESS [LENGTH] | VX | I]`: Set a watchpoint, or list them.
+    Watch(Option<Watchpoint>),
+    /// `unwatch read|write ADDRESS [LENGTH] | VX | I`: Clear a watchpoint.
+    Unwatch(Watchpoint),
+    /// `step [COUNT]`: Execute one or more instructions.
+    Step(usize),
+    /// `continue`: Run until a breakpoint is hit.
+    Continue,
+    /// `registers`: Show the registers, I, SP, stack and timers.
+    Registers,
+    /// `memory ADDRESS [LENGTH]`: Show a hexdump of memory.
+    Memory(usize, usize),
+    /// `list [ADDRESS]`: Disassemble around an address, or the PC.
+    List(Option<usize>),
+    /// `help`: Show the available commands.
+    Help,
+    /// `quit`: Leave the emulator.
+    Quit,
+}
+
+pub const HELP: &str = "\
+break [ADDRESS]           (b) set a breakpoint, or list them
+delete ADDRESS            (d) clear a breakpoint
+watch read ADDRESS [LEN]  (w) stop after memory is read, or written with 'write'
+watch VX | I              (w) stop after a register changes, or list watchpoints
+unwatch ...               (u) clear a watchpoint
+step [COUNT]              (s) execute instructions
+continue                  (c) run until a breakpoint is hit
+registers                 (r) show registers, I, SP, stack and timers
+memory ADDRESS [LENGTH]   (m) hexdump memory
+list [ADDRESS]            (l) disassemble around an address or the PC
+help                      (h) show this help
+quit                      (q) leave the emulator
+Numbers are decimal, or hexadecimal with a 0x prefix.";
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Err("Empty command, try 'help'.".to_string()),
+        };
+        match name {
+            "w" | "watch" => {
+                let words: Vec<&str> = words.collect();
+                if words.is_empty() {
+                    return Ok(Command::Watch(None));
+                }
+                return watchpoint(&words).map(|watchpoint| Command::Watch(Some(watchpoint)));
+            }
+            "u" | "unwatch" => {
+                let words: Vec<&str> = words.collect();
+                return watchpoint(&words).map(Command::Unwatch);
+            }
+            _ => (),
+        }
+        let args: Vec<usize> = words.map(number).collect::<Result<_, _>>()?;
+        let command = match (name, &args[..]) {
+            ("b", &[]) | ("break", &[]) => Command::Break(None),
+            ("b", &[address]) | ("break", &[address]) => Command::Break(Some(address)),
+            ("d", &[address]) | ("delete", &[address]) => Command::Delete(address),
+            ("s", &[]) | ("step", &[]) => Command::Step(1),
+            ("s", &[count]) | ("step", &[count]) => Command::Step(count),
+            ("c", &[]) | ("continue", &[]) => Command::Continue,
+            ("r", &[]) | ("registers", &[]) => Command::Registers,
+            ("m", &[address]) | ("memory", &[address]) => Command::Memory(address, 64),
+            ("m", &[address, length]) | ("memory", &[address, length]) => {
+                Command::Memory(address, length)
+            }
+            ("l", &[]) | ("list", &[]) => Command::List(None),
+            ("l", &[address]) | ("list", &[address]) => Command::List(Some(address)),
+            ("h", &[]) | ("help", &[]) => Command::Help,
+            ("q", &[]) | ("quit", &[]) => Command::Quit,
+            _ => return Err(format!("Invalid command '{}', try 'help'.", line.trim())),
+        };
+        Ok(command)
+    }
+}
+
+fn watchpoint(words: &[&str]) -> Result<Watchpoint, String> {
+    let args = words
+        .iter()
+        .skip(1)
+        .map(|word| number(word))
+        .collect::<Result<Vec<usize>, String>>()?;
+    let watchpoint = match (words.first().map(|word| word.to_uppercase()), &args[..]) {
+        (Some(ref kind), &[address]) if kind == "READ" => Watchpoint::Read(address, 1),
+        (Some(ref kind), &[address, len]) if kind == "READ" => Watchpoint::Read(address, len),
+        (Some(ref kind), &[address]) if kind == "WRITE" => Watchpoint::Write(address, 1),
+        (Some(ref kind), &[address, len]) if kind == "WRITE" => Watchpoint::Write(address, len),
+        (Some(ref kind), &[]) if kind == "I" => Watchpoint::Index,
+        (Some(ref kind), &[]) if kind.len() == 2 && kind.starts_with('V') => {
+            match u8::from_str_radix(&kind[1..], 16) {
+                Ok(x) => Watchpoint::Register(x),
+                Err(_) => return Err(format!("Invalid register '{}'.", kind)),
+            }
+        }
+        _ => return Err("Invalid watchpoint, try 'help'.".to_string()),
+    };
+    Ok(watchpoint)
+}
+
+fn number(word: &str) -> Result<usize, String> {
+    let parsed = match word.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => word.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number '{}'.", word))
+}
+
+/// Runs a `Chip8` under control of breakpoints and inspects its state.
+pub struct Debugger<D: Display = Framebuffer, B: Bus = Ram> {
+    pub chip8: Chip8<D, B>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    // Set after stopping, so resuming executes the instruction at a breakpoint.
+    resuming: bool,
+}
+
+impl<D: Display, B: Bus> Debugger<D, B> {
+    pub fn new(chip8: Chip8<D, B>) -> Self {
+        Debugger {
+            chip8,
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec![],
+            resuming: false,
+        }
+    }
+
+    /// Sets a breakpoint, returning false if there already was one.
+    pub fn set_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Clears a breakpoint, returning false if there was none.
+    pub fn clear_breakpoint(&mut self, address: usize) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> Vec<usize> {
+        self.breakpoints.iter().cloned().collect()
+    }
+
+    /// Sets a watchpoint, returning false if there already was one.
+    pub fn set_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.watchpoints.contains(&watchpoint) {
+            return false;
+        }
+        self.watchpoints.push(watchpoint);
+        true
+    }
+
+    /// Clears a watchpoint, returning false if there was none.
+    pub fn clear_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> Vec<Watchpoint> {
+        self.watchpoints.clone()
+    }
+
+    /// Executes a single instruction, ignoring breakpoints.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        self.chip8.cycle()?;
+        self.resuming = true;
+        Ok(())
+    }
+
+    /// Runs a frame like `Chip8::run_frame`, but stops before executing an instruction at a
+    /// breakpoint or after one that met a watchpoint. The timers are not ticked for a frame
+    /// that was stopped.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Result<Option<Stop>, Chip8Error> {
+        let mut redraw = false;
+        for _ in 0..cycles_per_frame {
+            if !self.resuming && self.breakpoints.contains(&self.chip8.pc) {
+                self.chip8.needs_redraw |= redraw;
+                self.resuming = true;
+                return Ok(Some(Stop::Breakpoint(self.chip8.pc)));
+            }
+            let (pc, registers, i) = (self.chip8.pc, self.chip8.registers, self.chip8.i);
+            if let Err(err) = self.chip8.cycle() {
+                self.chip8.needs_redraw |= redraw;
+                return Err(err);
+            }
+            self.resuming = false;
+            redraw |= self.chip8.needs_redraw;
+            if let Some(stop) = self.watched(pc, &registers, i) {
+                self.chip8.needs_redraw |= redraw;
+                return Ok(Some(stop));
+            }
+        }
+        self.chip8.needs_redraw = redraw;
+        self.chip8.tick_timers();
+        Ok(None)
+    }
+
+    // Finds the first watchpoint met by the instruction at `pc`, given the registers and I from
+    // before it was executed.
+    fn watched(&self, pc: usize, registers: &[u8; 16], i: usize) -> Option<Stop> {
+        let access = self.chip8.last_access();
+        for watchpoint in &self.watchpoints {
+            let stop = match (*watchpoint, access) {
+                (Watchpoint::Read(address, len), Some(access))
+                | (Watchpoint::Write(address, len), Some(access))
+                    if access.overlaps(address, len) =>
+                {
+                    let address = address.max(access.address);
+                    match (*watchpoint, access.kind) {
+                        (Watchpoint::Read(..), AccessKind::Read) => Stop::Read { pc, address },
+                        (Watchpoint::Write(..), AccessKind::Write) => Stop::Write { pc, address },
+                        _ => continue,
+                    }
+                }
+                (Watchpoint::Register(x), _)
+                    if registers[x as usize] != self.chip8.registers[x as usize] =>
+                {
+                    Stop::Register {
+                        pc,
+                        register: x,
+                        old: registers[x as usize],
+                        new: self.chip8.registers[x as usize],
+                    }
+                }
+                (Watchpoint::Index, _) if i != self.chip8.i => Stop::Index {
+                    pc,
+                    old: i,
+                    new: self.chip8.i,
+                },
+                _ => continue,
+            };
+            return Some(stop);
+        }
+        None
+    }
+
+    /// Executes a command, returning its output. `Continue` and `Quit` are left to the caller.
+    pub fn execute(&mut self, command: Command) -> String {
+        match command {
+            Command::Break(Some(address)) => {
+                self.set_breakpoint(address);
+                format!("Breakpoint at 0x{:03X}", address)
+            }
+            Command::Break(None) if self.breakpoints.is_empty() => "No breakpoints".to_string(),
+            Command::Break(None) => {
+                let addresses: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .map(|address| format!("0x{:03X}", address))
+                    .collect();
+                format!("Breakpoints: {}", addresses.join(" "))
+            }
+            Command::Delete(address) => {
+                if self.clear_breakpoint(address) {
+                    format!("Deleted breakpoint at 0x{:03X}", address)
+                } else {
+                    format!("No breakpoint at 0x{:03X}", address)
+                }
+            }
+            Command::Watch(Some(watchpoint)) => {
+                self.set_watchpoint(watchpoint);
+                format!("Watchpoint on {}", watchpoint)
+            }
+            Command::Watch(None) if self.watchpoints.is_empty() => "No watchpoints".to_string(),
+            Command::Watch(None) => {
+                let watchpoints: Vec<String> =
+                    self.watchpoints.iter().map(|w| w.to_string()).collect();
+                format!("Watchpoints: {}", watchpoints.join(", "))
+            }
+            Command::Unwatch(watchpoint) => {
+                if self.clear_watchpoint(watchpoint) {
+                    format!("Deleted watchpoint on {}", watchpoint)
+                } else {
+                    format!("No watchpoint on {}", watchpoint)
+                }
+            }
+            Command::Step(count) => {
+                for _ in 0..count {
+                    if let Err(err) = self.step() {
+                        return err.to_string();
+                    }
+                }
+                self.list(self.chip8.pc, 0, 1)
+            }
+            Command::Registers => self.registers(),
+            Command::Memory(address, length) => self.hexdump(address, length),
+            Command::List(address) => self.list(address.unwrap_or(self.chip8.pc), 5, 5),
+            Command::Help => HELP.to_string(),
+            Command::Continue | Command::Quit => String::new(),
+        }
+    }
+
+    /// Formats the registers, I, PC, SP, stack and timers.
+    pub fn registers(&self) -> String {
+        let chip8 = &self.chip8;
+        let mut out = format!(
+            "PC 0x{:03X}  I 0x{:03X}  SP {}  DT {}  ST {}",
+            chip8.pc, chip8.i, chip8.sp, chip8.delay_timer, chip8.sound_timer
+        );
+        for (row, registers) in chip8.registers.chunks(8).enumerate() {
+            let registers: Vec<String> = registers
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!("V{:X} {:02X}", row * 8 + i, v))
+                .collect();
+            out.push('\n');
+            out.push_str(&registers.join("  "));
+        }
+        let stack: Vec<String> = chip8.stack[..chip8.sp.min(chip8.stack.len())]
+            .iter()
+            .map(|address| format!("0x{:03X}", address))
+            .collect();
+        out.push_str("\nStack ");
+        out.push_str(&stack.join(" "));
+        out
+    }
+
+    // Reads up to `len` bytes from `address` without side effects, stopping at the end of
+    // memory.
+    fn peek(&self, address: usize, len: usize) -> Vec<u8> {
+        (address..address.saturating_add(len).min(MEMORY_SIZE))
+            .map(|address| self.chip8.bus.peek(address))
+            .collect()
+    }
+
+    /// Formats `length` bytes of memory from `address`, 16 to a line.
+    pub fn hexdump(&self, address: usize, length: usize) -> String {
+        let start = address.min(MEMORY_SIZE);
+        let end = address.saturating_add(length).min(MEMORY_SIZE);
+        let lines: Vec<String> = self
+            .peek(start, end - start)
+            .chunks(16)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+                format!("0x{:03X}  {}", start + i * 16, hex.join(" "))
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Disassembles `before` instructions before `address` and `after` from it, marking the PC
+    /// with `>` and breakpoints with `*`.
+    pub fn list(&self, address: usize, before: usize, after: usize) -> String {
+        // Instructions are disassembled forwards from an earlier address on the same alignment.
+        let mut at = address.saturating_sub(2 * before).min(MEMORY_SIZE);
+        let mut lines = vec![];
+        while at < MEMORY_SIZE && lines.len() < before + after {
+            let line = disasm::disassemble(&self.peek(at, 4), at);
+            let pc = if at == self.chip8.pc { '>' } else { ' ' };
+            let breakpoint = if self.breakpoints.contains(&at) {
+                '*'
+            } else {
+                ' '
+            };
+            lines.push(format!("{}{} {}", pc, breakpoint, line));
+            at += line.bytes.len();
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Increment;
+
+    fn debugger(program: &[u8]) -> Debugger {
+        let mut chip8 = Chip8::new();
+        chip8.load(program).unwrap();
+        Debugger::new(chip8)
+    }
+
+    #[test]
+    fn breakpoints() {
+        // 0x200: V0 += 1, JP 0x200
+        let mut debugger = debugger(&[0x70, 0x01, 0x12, 0x00]);
+        assert!(debugger.set_breakpoint(0x202));
+        assert!(!debugger.set_breakpoint(0x202));
+
+        let stop = debugger.run_frame(10).unwrap();
+        assert!(stop == Some(Stop::Breakpoint(0x202)));
+        assert!(debugger.chip8.registers[0] == 1);
+
+        // Continuing executes the instruction at the breakpoint before stopping again.
+        let stop = debugger.run_frame(10).unwrap();
+        assert!(stop == Some(Stop::Breakpoint(0x202)));
+        assert!(debugger.chip8.registers[0] == 2);
+
+        assert!(debugger.clear_breakpoint(0x202));
+        assert!(debugger.run_frame(10).unwrap().is_none());
+        assert!(debugger.chip8.registers[0] == 7);
+    }
+
+    #[test]
+    fn breakpoint_at_start() {
+        let mut debugger = debugger(&[0x70, 0x01, 0x12, 0x00]);
+        debugger.set_breakpoint(0x200);
+        let stop = debugger.run_frame(10).unwrap();
+        assert!(stop == Some(Stop::Breakpoint(0x200)));
+        assert!(debugger.chip8.registers[0] == 0);
+    }
+
+    #[test]
+    fn redraw_before_error() {
+        // 0x200: CLS, then an invalid instruction
+        let mut debugger = debugger(&[0x00, 0xE0, 0xFF, 0xFF]);
+        debugger.chip8.needs_redraw = false;
+        assert!(debugger.run_frame(10).is_err());
+        assert!(debugger.chip8.needs_redraw);
+    }
+
+    #[test]
+    fn watchpoints() {
+        // 0x200: LD I, 0x300; LD [I], V1; LD V2, [I]; ADD V3, 1; JP 0x200
+        let mut debugger = debugger(&[0xA3, 0x00, 0xF1, 0x55, 0xF2, 0x65, 0x73, 0x01, 0x12, 0x00]);
+        debugger.chip8.quirks.increment_i = Increment::None;
+        debugger.set_watchpoint(Watchpoint::Index);
+        debugger.set_watchpoint(Watchpoint::Write(0x301, 4));
+        debugger.set_watchpoint(Watchpoint::Read(0x2FF, 2));
+        debugger.set_watchpoint(Watchpoint::Register(3));
+
+        let stop = debugger.run_frame(100).unwrap();
+        assert!(
+            stop == Some(Stop::Index {
+                pc: 0x200,
+                old: 0,
+                new: 0x300,
+            })
+        );
+        let stop = debugger.run_frame(100).unwrap();
+        assert!(
+            stop == Some(Stop::Write {
+                pc: 0x202,
+                address: 0x301,
+            })
+        );
+        let stop = debugger.run_frame(100).unwrap();
+        assert!(
+            stop == Some(Stop::Read {
+                pc: 0x204,
+                address: 0x300,
+            })
+        );
+        let stop = debugger.run_frame(100).unwrap();
+        assert!(
+            stop == Some(Stop::Register {
+                pc: 0x206,
+                register: 3,
+                old: 0,
+                new: 1,
+            })
+        );
+        assert!(stop.unwrap().to_string() == "Watchpoint: 0x206 changed V3 from 0x00 to 0x01");
+
+        // Only reads and writes of the watched kind stop execution.
+        assert!(debugger.clear_watchpoint(Watchpoint::Index));
+        assert!(debugger.clear_watchpoint(Watchpoint::Register(3)));
+        assert!(debugger.clear_watchpoint(Watchpoint::Read(0x2FF, 2)));
+        assert!(!debugger.clear_watchpoint(Watchpoint::Read(0x2FF, 2)));
+        let stop = debugger.run_frame(100).unwrap();
+        assert!(stop.map(|stop| stop.to_string()) == Some("Watchpoint: 0x202 wrote 0x301".into()));
+    }
+
+    #[test]
+    fn commands() {
+        assert!(Command::parse("b 0x204") == Ok(Command::Break(Some(0x204))));
+        assert!(Command::parse("break") == Ok(Command::Break(None)));
+        assert!(Command::parse("step 10") == Ok(Command::Step(10)));
+        assert!(Command::parse("m 0x200 16") == Ok(Command::Memory(0x200, 16)));
+        assert!(Command::parse("c") == Ok(Command::Continue));
+        assert!(Command::parse("watch") == Ok(Command::Watch(None)));
+        assert!(
+            Command::parse("w write 0x300 3")
+                == Ok(Command::Watch(Some(Watchpoint::Write(0x300, 3))))
+        );
+        assert!(
+            Command::parse("w read 0x300") == Ok(Command::Watch(Some(Watchpoint::Read(0x300, 1))))
+        );
+        assert!(Command::parse("watch vA") == Ok(Command::Watch(Some(Watchpoint::Register(10)))));
+        assert!(Command::parse("unwatch I") == Ok(Command::Unwatch(Watchpoint::Index)));
+        assert!(Command::parse("watch VG").is_err());
+        assert!(Command::parse("unwatch").is_err());
+        assert!(Command::parse("step x").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+        assert!(Command::parse("").is_err());
+    }
+
+    #[test]
+    fn inspection() {
+        let mut debugger = debugger(&[0x63, 0x42, 0xA2, 0x34, 0x00, 0xE0]);
+        let out = debugger.execute(Command::Step(2));
+        assert!(out == ">  0x204  00E0      CLS");
+
+        let registers = debugger.registers();
+        assert!(registers.starts_with("PC 0x204  I 0x234  SP 0  DT 0  ST 0\n"));
+        assert!(registers.contains("V3 42"));
+
+        assert!(
+            debugger.hexdump(0x200, 20)
+                == "0x200  63 42 A2 34 00 E0 00 00 00 00 00 00 00 00 00 00\n0x210  00 00 00 00"
+        );
+
+        debugger.set_breakpoint(0x202);
+        let listing: Vec<String> = debugger
+            .list(0x204, 2, 1)
+            .lines()
+            .map(String::from)
+            .collect();
+        assert!(listing[0] == "   0x200  6342      LD V3, 0x42");
+        assert!(listing[1] == " * 0x202  A234      LD I, 0x234");
+        assert!(listing[2] == ">  0x204  00E0      CLS");
+    }
+}
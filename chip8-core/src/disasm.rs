@@ -0,0 +1,106 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use Instruction;
+
+/// One disassembled instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(
+            f,
+            "0x{:03X}  {:<8}  {}",
+            self.address,
+            hex.join(""),
+            self.text
+        )
+    }
+}
+
+/// Disassembles the instruction at the start of `bytes`, which is located at `address`.
+///
+/// Opcodes that are not instructions are shown as `DW` data; a trailing odd byte as `DB`.
+pub fn disassemble(bytes: &[u8], address: usize) -> Line {
+    let (bytes, text) = match Instruction::read(bytes) {
+        Some(instruction) => (instruction.to_bytes(), instruction.to_string()),
+        None if bytes.len() >= 2 => (
+            bytes[..2].to_vec(),
+            format!("DW 0x{:02X}{:02X}", bytes[0], bytes[1]),
+        ),
+        None => (
+            bytes.to_vec(),
+            bytes
+                .first()
+                .map(|b| format!("DB 0x{:02X}", b))
+                .unwrap_or_default(),
+        ),
+    };
+    Line {
+        address,
+        bytes,
+        text,
+    }
+}
+
+/// Disassembles a whole program loaded at `origin`, treating every word as an instruction.
+pub fn listing(program: &[u8], origin: usize) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < program.len() {
+        let line = disassemble(&program[offset..], origin + offset);
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8]) -> String {
+        disassemble(bytes, 0x200).text
+    }
+
+    #[test]
+    fn mnemonics() {
+        assert!(text(&[0x00, 0xE0]) == "CLS");
+        assert!(text(&[0x63, 0x42]) == "LD V3, 0x42");
+        assert!(text(&[0xD4, 0x55]) == "DRW V4, V5, 5");
+        assert!(text(&[0x23, 0x5F]) == "CALL 0x35F");
+        assert!(text(&[0x8A, 0xBE]) == "SHL VA, VB");
+        assert!(text(&[0xF7, 0x65]) == "LD V7, [I]");
+        assert!(text(&[0x00, 0xFF]) == "HIGH");
+        assert!(text(&[0xF3, 0x01]) == "PLANE 3");
+        assert!(text(&[0x00, 0x00]) == "DW 0x0000");
+        assert!(text(&[0x12]) == "DB 0x12");
+    }
+
+    #[test]
+    fn long_load() {
+        let line = disassemble(&[0xF0, 0x00, 0xBE, 0xEF, 0x00, 0xE0], 0x300);
+        assert!(line.text == "LD I, LONG 0xBEEF");
+        assert!(line.bytes.len() == 4);
+        assert!(line.to_string() == "0x300  F000BEEF  LD I, LONG 0xBEEF");
+    }
+
+    #[test]
+    fn listing_addresses() {
+        let lines = listing(
+            &[0x63, 0x42, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xEE, 0xFF],
+            0x200,
+        );
+        let addresses: Vec<usize> = lines.iter().map(|line| line.address).collect();
+        assert!(addresses == vec![0x200, 0x202, 0x206, 0x208]);
+        assert!(lines[3].text == "DB 0xFF");
+        assert!(lines[0].to_string() == "0x200  6342      LD V3, 0x42");
+    }
+}
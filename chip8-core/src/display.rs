@@ -0,0 +1,292 @@
+/// A rectangle of pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// The smallest rectangle containing both rectangles.
+    pub fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// The screen the machine draws on.
+///
+/// Each pixel holds one bit per XO-CHIP bitplane, so its value ranges from 0 to 3. Only the
+/// pixel accessors and dirty tracking must be implemented; the drawing operations are built
+/// on them, and can be overridden by displays with a faster way to do them.
+pub trait Display {
+    /// The width and height of the screen in pixels.
+    fn size(&self) -> (usize, usize);
+
+    /// Changes the resolution, clearing every pixel.
+    fn resize(&mut self, width: usize, height: usize);
+
+    fn pixel(&self, x: usize, y: usize) -> u8;
+
+    /// Sets a pixel, marking it dirty if its value changes.
+    fn set_pixel(&mut self, x: usize, y: usize, value: u8);
+
+    /// The area that changed since the last call, if any.
+    fn take_dirty(&mut self) -> Option<Rect>;
+
+    /// Turns off the given planes of every pixel.
+    fn clear(&mut self, planes: u8) {
+        let (width, height) = self.size();
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = self.pixel(x, y);
+                self.set_pixel(x, y, pixel & !planes);
+            }
+        }
+    }
+
+    /// XORs a sprite onto a plane at (`x`, `y`), returning whether any lit pixel was turned
+    /// off.
+    ///
+    /// `sprite` holds `width / 8` bytes per row, with the leftmost pixel in the most
+    /// significant bit. Pixels past the edges wrap around when `wrap` is set and are clipped
+    /// otherwise.
+    fn xor_sprite(
+        &mut self,
+        x: usize,
+        y: usize,
+        sprite: &[u8],
+        width: usize,
+        plane: u8,
+        wrap: bool,
+    ) -> bool {
+        let (screen_width, screen_height) = self.size();
+        let mut collision = false;
+        for (i, row) in sprite.chunks(width / 8).enumerate() {
+            for j in 0..width {
+                if (row[j / 8] >> (7 - j % 8)) & 1 == 0 {
+                    continue;
+                }
+                let (mut x, mut y) = (x + j, y + i);
+                if wrap {
+                    x %= screen_width;
+                    y %= screen_height;
+                } else if x >= screen_width || y >= screen_height {
+                    continue;
+                }
+                let pixel = self.pixel(x, y);
+                collision |= pixel & plane != 0;
+                self.set_pixel(x, y, pixel ^ plane);
+            }
+        }
+        collision
+    }
+
+    /// Moves the given planes `dx` pixels right and `dy` pixels down, turning them off where
+    /// nothing moves in.
+    fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let (width, height) = self.size();
+        let (width, height) = (width as isize, height as isize);
+        // Every pixel is visited before the pixel it is moved to, so it can be done in place.
+        for row in 0..height {
+            let y = if dy > 0 { height - 1 - row } else { row };
+            for column in 0..width {
+                let x = if dx > 0 { width - 1 - column } else { column };
+                let (sx, sy) = (x - dx, y - dy);
+                let mut pixel = self.pixel(x as usize, y as usize) & !planes;
+                if (0..width).contains(&sx) && (0..height).contains(&sy) {
+                    pixel |= self.pixel(sx as usize, sy as usize) & planes;
+                }
+                self.set_pixel(x as usize, y as usize, pixel);
+            }
+        }
+    }
+}
+
+/// The default display: an array of pixels, row after row at the current resolution.
+pub struct Framebuffer {
+    pub pixels: [u8; 128 * 64],
+    width: usize,
+    height: usize,
+    dirty: Option<Rect>,
+}
+
+impl Framebuffer {
+    /// A blank low resolution (64x32) screen.
+    pub fn new() -> Self {
+        Framebuffer {
+            pixels: [0; 128 * 64],
+            width: 64,
+            height: 32,
+            dirty: None,
+        }
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty = Some(Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        });
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for Framebuffer {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn resize(&mut self, width: usize, height: usize) {
+        assert!(width * height <= self.pixels.len(), "Resolution too large.");
+        self.width = width;
+        self.height = height;
+        self.pixels = [0; 128 * 64];
+        self.mark_all_dirty();
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[self.width * y + x]
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        let index = self.width * y + x;
+        if self.pixels[index] != value {
+            self.pixels[index] = value;
+            let pixel = Rect {
+                x,
+                y,
+                width: 1,
+                height: 1,
+            };
+            self.dirty = Some(self.dirty.map_or(pixel, |dirty| dirty.union(pixel)));
+        }
+    }
+
+    fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    fn clear(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+        self.mark_all_dirty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "alloc")]
+    use alloc::vec::Vec;
+    #[cfg(feature = "alloc")]
+    use {screen, Chip8, Quirks};
+
+    // A display with nothing but the required methods.
+    #[cfg(feature = "alloc")]
+    struct Grid {
+        rows: Vec<Vec<u8>>,
+    }
+
+    #[cfg(feature = "alloc")]
+    impl Display for Grid {
+        fn size(&self) -> (usize, usize) {
+            (self.rows[0].len(), self.rows.len())
+        }
+
+        fn resize(&mut self, width: usize, height: usize) {
+            self.rows = vec![vec![0; width]; height];
+        }
+
+        fn pixel(&self, x: usize, y: usize) -> u8 {
+            self.rows[y][x]
+        }
+
+        fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+            self.rows[y][x] = value;
+        }
+
+        fn take_dirty(&mut self) -> Option<Rect> {
+            None
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn custom_display() {
+        let program = include_bytes!("../../data/10print.ch8");
+        let mut chip8 = Chip8::new();
+        chip8.load(program).unwrap();
+        let grid = Grid { rows: vec![vec![]] };
+        let mut custom = Chip8::with_display(Quirks::default(), grid);
+        custom.load(program).unwrap();
+        for _ in 0..30 {
+            chip8.run_frame(20).unwrap();
+            custom.run_frame(20).unwrap();
+        }
+        assert!(screen::to_text(&custom) == screen::to_text(&chip8));
+        assert!(custom.save_state() == chip8.save_state());
+    }
+
+    #[test]
+    fn dirty_rect() {
+        let mut display = Framebuffer::new();
+        assert!(display.take_dirty().is_none());
+        display.set_pixel(3, 4, 1);
+        display.set_pixel(10, 2, 1);
+        display.set_pixel(5, 5, 0);
+        let dirty = Rect {
+            x: 3,
+            y: 2,
+            width: 8,
+            height: 3,
+        };
+        assert!(display.take_dirty() == Some(dirty));
+        assert!(display.take_dirty().is_none());
+
+        // A sprite drawn over itself changes nothing overall, but both draws are reported.
+        let sprite = [0xC0, 0xC0];
+        assert!(!display.xor_sprite(20, 20, &sprite, 8, 1, false));
+        assert!(display.xor_sprite(20, 20, &sprite, 8, 1, false));
+        let dirty = Rect {
+            x: 20,
+            y: 20,
+            width: 2,
+            height: 2,
+        };
+        assert!(display.take_dirty() == Some(dirty));
+
+        display.resize(128, 64);
+        assert!(display.size() == (128, 64));
+        assert!(display.pixel(3, 4) == 0);
+        assert!(display.take_dirty().map(|dirty| dirty.width) == Some(128));
+    }
+
+    #[test]
+    fn scroll_in_place() {
+        let mut display = Framebuffer::new();
+        display.set_pixel(0, 0, 3);
+        display.set_pixel(63, 31, 1);
+        display.scroll(2, 1, 1);
+        assert!(display.pixel(0, 0) == 2);
+        assert!(display.pixel(2, 1) == 1);
+        assert!(display.pixel(63, 31) == 0);
+        display.scroll(-2, -1, 3);
+        assert!(display.pixel(0, 0) == 1);
+        assert!(display.pixels.iter().filter(|&&pixel| pixel != 0).count() == 1);
+    }
+}
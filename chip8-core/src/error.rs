@@ -0,0 +1,107 @@
+use core::error::Error;
+use core::fmt;
+
+/// An instruction that could not be executed, or a program that could not be loaded.
+///
+/// The machine is left exactly as it was before the failing instruction or load, with the PC
+/// still pointing at the instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The opcode does not match any supported instruction.
+    UnknownOpcode { pc: usize, opcode: u16 },
+    /// A subroutine call was made with the stack already full.
+    StackOverflow { pc: usize, opcode: u16 },
+    /// A subroutine return was made with the stack empty.
+    StackUnderflow { pc: usize, opcode: u16 },
+    /// The instruction accesses memory past the end of the address space.
+    MemoryOutOfBounds {
+        pc: usize,
+        opcode: u16,
+        address: usize,
+    },
+    /// The instruction writes to memory the bus has made read-only.
+    ReadOnly {
+        pc: usize,
+        opcode: u16,
+        address: usize,
+    },
+    /// The PC points past the end of the address space, so no opcode can be fetched.
+    PcOutOfBounds { pc: usize },
+    /// The program loaded at `pc` runs past the end of the address space.
+    ProgramTooLarge { pc: usize, size: usize },
+}
+
+impl Chip8Error {
+    pub fn pc(&self) -> usize {
+        match *self {
+            Chip8Error::UnknownOpcode { pc, .. }
+            | Chip8Error::StackOverflow { pc, .. }
+            | Chip8Error::StackUnderflow { pc, .. }
+            | Chip8Error::MemoryOutOfBounds { pc, .. }
+            | Chip8Error::ReadOnly { pc, .. }
+            | Chip8Error::PcOutOfBounds { pc }
+            | Chip8Error::ProgramTooLarge { pc, .. } => pc,
+        }
+    }
+
+    pub fn opcode(&self) -> Option<u16> {
+        match *self {
+            Chip8Error::UnknownOpcode { opcode, .. }
+            | Chip8Error::StackOverflow { opcode, .. }
+            | Chip8Error::StackUnderflow { opcode, .. }
+            | Chip8Error::MemoryOutOfBounds { opcode, .. }
+            | Chip8Error::ReadOnly { opcode, .. } => Some(opcode),
+            Chip8Error::PcOutOfBounds { .. } | Chip8Error::ProgramTooLarge { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::UnknownOpcode { pc, opcode } => write!(
+                f,
+                "unsupported instruction 0x{:04X} at 0x{:03X}",
+                opcode, pc
+            ),
+            Chip8Error::StackOverflow { pc, opcode } => write!(
+                f,
+                "stack overflow executing 0x{:04X} at 0x{:03X}",
+                opcode, pc
+            ),
+            Chip8Error::StackUnderflow { pc, opcode } => write!(
+                f,
+                "stack underflow executing 0x{:04X} at 0x{:03X}",
+                opcode, pc
+            ),
+            Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "memory access to 0x{:X} out of bounds executing 0x{:04X} at 0x{:03X}",
+                address, opcode, pc
+            ),
+            Chip8Error::ReadOnly {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "write to read-only memory at 0x{:X} executing 0x{:04X} at 0x{:03X}",
+                address, opcode, pc
+            ),
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "program counter 0x{:X} out of bounds", pc)
+            }
+            Chip8Error::ProgramTooLarge { pc, size } => write!(
+                f,
+                "program of {} bytes does not fit in memory from 0x{:03X}",
+                size, pc
+            ),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
@@ -0,0 +1,589 @@
+use std::io;
+use std::io::prelude::*;
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::prelude::v1::*;
+
+use {Bus, Chip8Error, Debugger, Display, Stop, Watchpoint, MEMORY_SIZE};
+
+// Register numbers: V0 to VF, then I, PC and SP.
+const REGISTER_I: usize = 16;
+const REGISTER_PC: usize = 17;
+const REGISTER_SP: usize = 18;
+const REGISTER_COUNT: usize = 19;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// What the attached debugger wants the emulator to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GdbState {
+    /// The machine is halted, waiting for commands.
+    Stopped,
+    /// The machine runs until a breakpoint, watchpoint or interrupt.
+    Running,
+    /// The debugger has detached or disconnected; the machine runs freely.
+    Detached,
+    /// The debugger has asked to end the emulator.
+    Killed,
+}
+
+/// A GDB remote serial protocol server controlling a `Debugger`.
+///
+/// Registers are numbered V0 to VF (8 bits), I (16 bits), PC (16 bits) and SP (8 bits).
+/// Software and hardware breakpoints map to `Debugger` breakpoints, write and read
+/// watchpoints to `Watchpoint::Write` and `Watchpoint::Read`. The register layout is described
+/// by a target description, so GDB needs no CHIP-8 support of its own. That description cannot
+/// name an architecture GDB knows, so GDB reads registers in the byte order of its default
+/// architecture, and they are transferred little-endian to match the hosts GDB usually runs
+/// on.
+///
+/// Memory reads and writes reach all `MEMORY_SIZE` (64 KiB) bytes rather than the 4 KiB of the
+/// original machine. This is deliberate: XO-CHIP programs use the whole address space.
+///
+/// The server never blocks: call `poll` once per frame and run the machine while the state is
+/// `Running`, reporting stops with `stopped` and errors with `faulted`.
+pub struct GdbServer {
+    stream: TcpStream,
+    input: Vec<u8>,
+    state: GdbState,
+}
+
+impl GdbServer {
+    /// Waits for a debugger to connect to `port` on localhost.
+    pub fn listen(port: u16) -> io::Result<GdbServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let (stream, _) = listener.accept()?;
+        GdbServer::new(stream)
+    }
+
+    pub fn new(stream: TcpStream) -> io::Result<GdbServer> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(GdbServer {
+            stream,
+            input: vec![],
+            state: GdbState::Stopped,
+        })
+    }
+
+    pub fn state(&self) -> GdbState {
+        self.state
+    }
+
+    /// Handles everything the debugger has sent since the last call.
+    pub fn poll<D: Display, B: Bus>(&mut self, debugger: &mut Debugger<D, B>) -> io::Result<()> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    self.state = GdbState::Detached;
+                    return Ok(());
+                }
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        while !self.input.is_empty() {
+            match self.input[0] {
+                // A Ctrl-C from the debugger interrupts a running machine.
+                0x03 => {
+                    self.input.remove(0);
+                    if self.state == GdbState::Running {
+                        self.state = GdbState::Stopped;
+                        self.send(&format!("S{:02x}", SIGINT))?;
+                    }
+                }
+                b'$' => {
+                    let end = match self.input.iter().position(|&b| b == b'#') {
+                        Some(end) if self.input.len() >= end + 3 => end,
+                        _ => break,
+                    };
+                    let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                    let data = String::from_utf8_lossy(&packet[1..end]).into_owned();
+                    let checksum = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    if checksum != Some(checksum_of(data.as_bytes())) {
+                        self.stream.write_all(b"-")?;
+                        continue;
+                    }
+                    self.stream.write_all(b"+")?;
+                    if let Some(reply) = self.handle(&data, debugger) {
+                        self.send(&reply)?;
+                    }
+                }
+                // Acknowledgements and noise between packets.
+                _ => {
+                    self.input.remove(0);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports that the machine stopped at a breakpoint or watchpoint.
+    pub fn stopped(&mut self, stop: Stop) -> io::Result<()> {
+        self.state = GdbState::Stopped;
+        let reply = match stop {
+            Stop::Breakpoint(_) | Stop::Register { .. } | Stop::Index { .. } => {
+                format!("S{:02x}", SIGTRAP)
+            }
+            Stop::Write { address, .. } => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+            Stop::Read { address, .. } => format!("T{:02x}rwatch:{:x};", SIGTRAP, address),
+        };
+        self.send(&reply)
+    }
+
+    /// Reports that the machine could not execute an instruction.
+    pub fn faulted(&mut self, err: Chip8Error) -> io::Result<()> {
+        self.state = GdbState::Stopped;
+        let signal = match err {
+            Chip8Error::UnknownOpcode { .. } => SIGILL,
+            _ => SIGSEGV,
+        };
+        self.send(&format!("S{:02x}", signal))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    // Handles one packet, returning the reply, or None when the reply is a later stop.
+    fn handle<D: Display, B: Bus>(
+        &mut self,
+        data: &str,
+        debugger: &mut Debugger<D, B>,
+    ) -> Option<String> {
+        let command = data.get(..1).unwrap_or("");
+        let args = data.get(1..).unwrap_or("");
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => (0..REGISTER_COUNT)
+                .map(|n| register(debugger, n).unwrap())
+                .collect(),
+            "G" => {
+                let mut rest = args;
+                for n in 0..REGISTER_COUNT {
+                    let size = register(debugger, n).unwrap().len();
+                    if rest.len() < size {
+                        return Some("E01".to_string());
+                    }
+                    if set_register(debugger, n, &rest[..size]).is_none() {
+                        return Some("E01".to_string());
+                    }
+                    rest = &rest[size..];
+                }
+                "OK".to_string()
+            }
+            "p" => hex_number(args)
+                .and_then(|n| register(debugger, n))
+                .unwrap_or_else(|| "E01".to_string()),
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let n = parts.next().and_then(hex_number);
+                match (n, parts.next()) {
+                    (Some(n), Some(value)) if set_register(debugger, n, value).is_some() => {
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match address_and_length(args) {
+                Some((address, len)) if in_memory(address, len) => (address..address + len)
+                    .map(|address| format!("{:02x}", debugger.chip8.bus.peek(address)))
+                    .collect(),
+                _ => "E01".to_string(),
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                let range = parts.next().and_then(address_and_length);
+                let bytes = parts.next().and_then(hex_bytes);
+                match (range, bytes) {
+                    (Some((address, len)), Some(ref bytes))
+                        if bytes.len() == len && in_memory(address, len) =>
+                    {
+                        for (offset, &byte) in bytes.iter().enumerate() {
+                            debugger.chip8.bus.poke(address + offset, byte);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "s" => {
+                // A step that fails reports the error like a stop would.
+                return Some(match debugger.step() {
+                    Ok(()) => format!("S{:02x}", SIGTRAP),
+                    Err(Chip8Error::UnknownOpcode { .. }) => format!("S{:02x}", SIGILL),
+                    Err(_) => format!("S{:02x}", SIGSEGV),
+                });
+            }
+            "c" => {
+                self.state = GdbState::Running;
+                return None;
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args, debugger),
+            "D" => {
+                self.state = GdbState::Detached;
+                "OK".to_string()
+            }
+            "k" => {
+                self.state = GdbState::Killed;
+                return None;
+            }
+            "H" => "OK".to_string(),
+            "q" => query(args),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn breakpoint<D: Display, B: Bus>(
+        &mut self,
+        insert: bool,
+        args: &str,
+        debugger: &mut Debugger<D, B>,
+    ) -> String {
+        let parts: Vec<&str> = args.split(',').collect();
+        let (kind, address, len) = match parts[..] {
+            [kind, address, len] => match (hex_number(address), hex_number(len)) {
+                (Some(address), Some(len)) => (kind, address, len.max(1)),
+                _ => return "E01".to_string(),
+            },
+            _ => return "E01".to_string(),
+        };
+        let watchpoint = match kind {
+            // Software and hardware breakpoints.
+            "0" | "1" => {
+                if insert {
+                    debugger.set_breakpoint(address);
+                } else {
+                    debugger.clear_breakpoint(address);
+                }
+                return "OK".to_string();
+            }
+            "2" => Watchpoint::Write(address, len),
+            "3" => Watchpoint::Read(address, len),
+            _ => return String::new(),
+        };
+        if insert {
+            debugger.set_watchpoint(watchpoint);
+        } else {
+            debugger.clear_watchpoint(watchpoint);
+        }
+        "OK".to_string()
+    }
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return "PacketSize=1000;qXfer:features:read+".to_string();
+    }
+    if let Some(rest) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        let description = target_description();
+        return match address_and_length(rest) {
+            Some((offset, len)) => match offset.checked_add(len) {
+                None => "E01".to_string(),
+                Some(_) if offset >= description.len() => "l".to_string(),
+                Some(end) if end >= description.len() => format!("l{}", &description[offset..]),
+                Some(end) => format!("m{}", &description[offset..end]),
+            },
+            None => "E01".to_string(),
+        };
+    }
+    match args {
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn target_description() -> String {
+    let mut registers = String::new();
+    for x in 0..16 {
+        registers.push_str(&format!(
+            "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>",
+            x
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">{}\
+         <reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+         <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\
+         </feature></target>",
+        registers
+    )
+}
+
+fn register<D: Display, B: Bus>(debugger: &Debugger<D, B>, n: usize) -> Option<String> {
+    let chip8 = &debugger.chip8;
+    let value = match n {
+        0..=15 => return Some(format!("{:02x}", chip8.registers[n])),
+        REGISTER_I => chip8.i,
+        REGISTER_PC => chip8.pc,
+        REGISTER_SP => return Some(format!("{:02x}", chip8.sp)),
+        _ => return None,
+    };
+    Some(hex_string(&(value as u16).to_le_bytes()))
+}
+
+fn set_register<D: Display, B: Bus>(
+    debugger: &mut Debugger<D, B>,
+    n: usize,
+    hex: &str,
+) -> Option<()> {
+    // Values are as wide as the register and little-endian, like the ones sent by `register`.
+    let bytes = hex_bytes(hex)?;
+    if hex.len() != register(debugger, n)?.len() {
+        return None;
+    }
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | byte as usize);
+    let chip8 = &mut debugger.chip8;
+    match n {
+        0..=15 if value <= 0xFF => chip8.registers[n] = value as u8,
+        REGISTER_I if value <= 0xFFFF => chip8.i = value,
+        REGISTER_PC if value <= 0xFFFF => chip8.pc = value,
+        REGISTER_SP if value <= chip8.stack.len() => chip8.sp = value,
+        _ => return None,
+    }
+    Some(())
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, &b| sum.wrapping_add(b))
+}
+
+fn hex_number(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+fn address_and_length(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let address = parts.next().and_then(hex_number)?;
+    let len = parts.next().and_then(hex_number)?;
+    Some((address, len))
+}
+
+// Whether the range lies in memory, which client values near usize::MAX must not wrap into.
+fn in_memory(address: usize, len: usize) -> bool {
+    address
+        .checked_add(len)
+        .is_some_and(|end| end <= MEMORY_SIZE)
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+    use Chip8;
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum_of(data.as_bytes()))
+    }
+
+    // Counts the acknowledgements and complete packets in what the server wrote.
+    fn count_replies(output: &[u8]) -> (usize, usize) {
+        let (mut acks, mut packets, mut i) = (0, 0, 0);
+        while i < output.len() {
+            match output[i] {
+                b'+' => acks += 1,
+                b'$' => match output[i..].iter().position(|&b| b == b'#') {
+                    Some(end) if i + end + 3 <= output.len() => {
+                        packets += 1;
+                        i += end + 2;
+                    }
+                    _ => break,
+                },
+                _ => (),
+            }
+            i += 1;
+        }
+        (acks, packets)
+    }
+
+    // Sends packets to the server and polls it until every packet is acknowledged and
+    // `replies` packets came back, returning everything it wrote.
+    fn exchange(
+        server: &mut GdbServer,
+        client: &mut TcpStream,
+        debugger: &mut Debugger,
+        packets: &[&str],
+        replies: usize,
+    ) -> String {
+        for data in packets {
+            client.write_all(packet(data).as_bytes()).unwrap();
+        }
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut output = vec![];
+        loop {
+            let (acks, received) = count_replies(&output);
+            if acks >= packets.len() && received >= replies {
+                break;
+            }
+            assert!(Instant::now() < deadline);
+            server.poll(debugger).unwrap();
+            let mut buffer = [0; 4096];
+            let n = match client.read(&mut buffer) {
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => 0,
+                result => result.unwrap(),
+            };
+            output.extend_from_slice(&buffer[..n]);
+        }
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    fn connect() -> (GdbServer, TcpStream) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.set_nonblocking(true).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (GdbServer::new(stream).unwrap(), client)
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let (mut server, mut client) = connect();
+        let mut chip8 = Chip8::new();
+        chip8.load(&[0x63, 0x42, 0x12, 0x00]).unwrap();
+        chip8.i = 0x234;
+        let mut debugger = Debugger::new(chip8);
+
+        let reply = exchange(&mut server, &mut client, &mut debugger, &["g"], 1);
+        let registers = format!("+${}34020002{:02x}", "00".repeat(16), 0);
+        assert!(reply.starts_with(&registers));
+
+        let reply = exchange(&mut server, &mut client, &mut debugger, &["m200,4"], 1);
+        assert!(reply == format!("+{}", packet("63421200")));
+
+        let reply = exchange(
+            &mut server,
+            &mut client,
+            &mut debugger,
+            &["M300,2:abcd", "P3=7f", "P11=0402"],
+            3,
+        );
+        assert!(reply == format!("+{}+{}+{}", packet("OK"), packet("OK"), packet("OK")));
+        assert!(debugger.chip8.bus.bytes[0x300..0x302] == [0xAB, 0xCD]);
+        assert!(debugger.chip8.registers[3] == 0x7F);
+        assert!(debugger.chip8.pc == 0x204);
+
+        // GDB decodes a register from the bytes in the reply, lowest address first, in the
+        // byte order of its default little-endian architecture.
+        let reply = exchange(&mut server, &mut client, &mut debugger, &["p10", "p11"], 2);
+        let values: Vec<u16> = reply
+            .split('$')
+            .skip(1)
+            .map(|packet| {
+                let bytes = hex_bytes(&packet[..4]).unwrap();
+                u16::from_le_bytes([bytes[0], bytes[1]])
+            })
+            .collect();
+        assert!(values == [0x234, 0x204]);
+
+        let reply = exchange(
+            &mut server,
+            &mut client,
+            &mut debugger,
+            &["P10=34", "P3=07f"],
+            2,
+        );
+        assert!(reply == format!("+{}+{}", packet("E01"), packet("E01")));
+
+        let reply = exchange(&mut server, &mut client, &mut debugger, &["m10000,1"], 1);
+        assert!(reply == format!("+{}", packet("E01")));
+
+        // Ranges that wrap around the address space are refused rather than overflowing.
+        let reply = exchange(
+            &mut server,
+            &mut client,
+            &mut debugger,
+            &["mffffffffffffffff,2", "Mffffffffffffffff,2:abcd"],
+            2,
+        );
+        assert!(reply == format!("+{}+{}", packet("E01"), packet("E01")));
+    }
+
+    #[test]
+    fn execution() {
+        let (mut server, mut client) = connect();
+        let mut chip8 = Chip8::new();
+        // 0x200: V3 += 1; JP 0x200
+        chip8.load(&[0x73, 0x01, 0x12, 0x00]).unwrap();
+        let mut debugger = Debugger::new(chip8);
+
+        let reply = exchange(&mut server, &mut client, &mut debugger, &["s"], 1);
+        assert!(reply == format!("+{}", packet("S05")));
+        assert!(debugger.chip8.pc == 0x202);
+
+        let reply = exchange(
+            &mut server,
+            &mut client,
+            &mut debugger,
+            &["Z0,200,2", "c"],
+            1,
+        );
+        assert!(reply == format!("+{}+", packet("OK")));
+        assert!(server.state() == GdbState::Running);
+        let stop = debugger.run_frame(10).unwrap().unwrap();
+        server.stopped(stop).unwrap();
+        assert!(server.state() == GdbState::Stopped);
+        assert!(debugger.chip8.pc == 0x200);
+
+        // The stop reply arrives before the acknowledgement of the next packet.
+        let reply = exchange(&mut server, &mut client, &mut debugger, &["c"], 1);
+        assert!(reply == format!("{}+", packet("S05")));
+
+        client.write_all(&[0x03]).unwrap();
+        let reply = exchange(
+            &mut server,
+            &mut client,
+            &mut debugger,
+            &["z0,200,2", "c"],
+            2,
+        );
+        assert!(reply.contains(&packet("S02")));
+        assert!(debugger.breakpoints().is_empty());
+
+        let reply = exchange(&mut server, &mut client, &mut debugger, &["D"], 1);
+        assert!(reply == format!("+{}", packet("OK")));
+        assert!(server.state() == GdbState::Detached);
+    }
+
+    #[test]
+    fn target_xml() {
+        let description = target_description();
+        let reply = query(&format!("Xfer:features:read:target.xml:0,{:x}", 1000));
+        assert!(reply == format!("l{}", description));
+        let reply = query("Xfer:features:read:target.xml:0,10");
+        assert!(reply == format!("m{}", &description[..16]));
+        let reply = query("Xfer:features:read:target.xml:10,ffffffffffffffff");
+        assert!(reply == "E01");
+    }
+}
@@ -0,0 +1,224 @@
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use {Bus, Chip8, Chip8Error, Display, Instruction, KeyEvent};
+
+/// A condition that ends a headless run before its frame limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Until {
+    /// The PC reaches an address, before the instruction there runs.
+    Pc(usize),
+    /// The program jumps to the current instruction, the usual way to halt a CHIP-8 program.
+    Idle,
+    /// The program exits with 00FD.
+    Exit,
+}
+
+impl Until {
+    /// Parses `pc=ADDRESS`, `idle` or `exit`.
+    pub fn parse(text: &str) -> Option<Until> {
+        match text {
+            "idle" => Some(Until::Idle),
+            "exit" => Some(Until::Exit),
+            _ => {
+                let address = text.strip_prefix("pc=")?;
+                let address = match address.strip_prefix("0x") {
+                    Some(hex) => usize::from_str_radix(hex, 16).ok()?,
+                    None => address.parse().ok()?,
+                };
+                Some(Until::Pc(address))
+            }
+        }
+    }
+
+    fn reached<D: Display, B: Bus>(&self, chip8: &Chip8<D, B>) -> bool {
+        match *self {
+            Until::Pc(address) => chip8.pc == address,
+            Until::Idle => {
+                chip8.instruction_at(chip8.pc) == Some(Instruction::Jump(chip8.pc as u16))
+            }
+            Until::Exit => chip8.exited,
+        }
+    }
+}
+
+/// A script line that could not be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid input script line {}", self.line)
+    }
+}
+
+impl Error for ScriptError {}
+
+/// Scripted input for a headless run: keys pressed and released at the start of frames.
+///
+/// Each line is `FRAME down KEY` or `FRAME up KEY`, with the key in hexadecimal. Lines may
+/// come in any order; `#` starts a comment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Script {
+    events: Vec<KeyEvent>,
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Script, ScriptError> {
+        let mut events = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let invalid = ScriptError { line: index + 1 };
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (frame, pressed, key) = match words[..] {
+                [] => continue,
+                [frame, "down", key] => (frame, true, key),
+                [frame, "up", key] => (frame, false, key),
+                _ => return Err(invalid),
+            };
+            let key = u8::from_str_radix(key, 16).map_err(|_| invalid)?;
+            if key > 0xF {
+                return Err(invalid);
+            }
+            events.push(KeyEvent {
+                frame: frame.parse().map_err(|_| invalid)?,
+                cycle: 0,
+                key,
+                pressed,
+            });
+        }
+        // A stable sort keeps the order of events within a frame.
+        events.sort_by_key(|event| event.frame);
+        Ok(Script { events })
+    }
+}
+
+/// How a headless run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of frames that ran, including a frame cut short by the condition.
+    pub frames: u64,
+    /// Whether the run ended because its condition was met.
+    pub condition_met: bool,
+}
+
+/// Runs a program without a display, for a number of frames or until a condition is met.
+pub struct Headless {
+    pub cycles_per_frame: usize,
+    pub frames: u64,
+    pub until: Option<Until>,
+    pub script: Script,
+}
+
+impl Headless {
+    pub fn run<D: Display, B: Bus>(&self, chip8: &mut Chip8<D, B>) -> Result<Summary, Chip8Error> {
+        let mut events = self.script.events.iter().peekable();
+        for frame in 0..self.frames {
+            while let Some(event) = events.next_if(|event| event.frame <= frame) {
+                if event.pressed {
+                    chip8.key_down(event.key);
+                } else {
+                    chip8.key_up(event.key);
+                }
+            }
+            let mut redraw = false;
+            for _ in 0..self.cycles_per_frame {
+                if self.until.is_some_and(|until| until.reached(chip8)) {
+                    chip8.needs_redraw |= redraw;
+                    return Ok(Summary {
+                        frames: frame + 1,
+                        condition_met: true,
+                    });
+                }
+                if let Err(err) = chip8.cycle() {
+                    chip8.needs_redraw |= redraw;
+                    return Err(err);
+                }
+                redraw |= chip8.needs_redraw;
+            }
+            chip8.needs_redraw = redraw;
+            chip8.tick_timers();
+        }
+        Ok(Summary {
+            frames: self.frames,
+            condition_met: self.until.is_some_and(|until| until.reached(chip8)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headless(frames: u64, until: Option<Until>, script: &str) -> Headless {
+        Headless {
+            cycles_per_frame: 10,
+            frames,
+            until,
+            script: Script::parse(script).unwrap(),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert!(Until::parse("idle") == Some(Until::Idle));
+        assert!(Until::parse("exit") == Some(Until::Exit));
+        assert!(Until::parse("pc=0x2A4") == Some(Until::Pc(0x2A4)));
+        assert!(Until::parse("pc=512") == Some(Until::Pc(512)));
+        assert!(Until::parse("pc=").is_none());
+        assert!(Until::parse("never").is_none());
+
+        let script = Script::parse("# jump\n5 down A\n2 up 1 # early\n\n5 up A\n").unwrap();
+        let frames: Vec<(u64, u8, bool)> = script
+            .events
+            .iter()
+            .map(|event| (event.frame, event.key, event.pressed))
+            .collect();
+        assert!(frames == [(2, 1, false), (5, 0xA, true), (5, 0xA, false)]);
+        assert!(Script::parse("1 down\n") == Err(ScriptError { line: 1 }));
+        assert!(Script::parse("\n1 down 10\n") == Err(ScriptError { line: 2 }));
+        assert!(Script::parse("x up 1\n") == Err(ScriptError { line: 1 }));
+    }
+
+    #[test]
+    fn run() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load(include_bytes!("../../data/10print.ch8"))
+            .unwrap();
+        let summary = headless(20, None, "").run(&mut chip8).unwrap();
+        assert!(summary.frames == 20 && !summary.condition_met);
+
+        // 0x200: V0 = 0; 0x202: wait for a key into V1; 0x204: jump to itself.
+        let program = [0x60, 0x00, 0xF1, 0x0A, 0x12, 0x04];
+        chip8.load(&program).unwrap();
+        let summary = headless(20, Some(Until::Idle), "3 down 7\n")
+            .run(&mut chip8)
+            .unwrap();
+        assert!(summary.frames == 4 && summary.condition_met);
+        assert!(chip8.registers[1] == 7);
+
+        chip8.load(&program).unwrap();
+        let summary = headless(20, Some(Until::Pc(0x202)), "")
+            .run(&mut chip8)
+            .unwrap();
+        assert!(summary.frames == 1 && summary.condition_met);
+        assert!(chip8.pc == 0x202);
+
+        chip8.load(&[0x00, 0xFD]).unwrap();
+        let summary = headless(3, Some(Until::Exit), "").run(&mut chip8).unwrap();
+        assert!(summary.condition_met);
+
+        chip8.load(&[0xFF, 0xFF]).unwrap();
+        assert!(headless(3, None, "").run(&mut chip8).is_err());
+
+        // The screen cleared before the error still has to be drawn.
+        chip8.load(&[0x00, 0xE0, 0xFF, 0xFF]).unwrap();
+        chip8.needs_redraw = false;
+        assert!(headless(3, None, "").run(&mut chip8).is_err());
+        assert!(chip8.needs_redraw);
+    }
+}
@@ -0,0 +1,387 @@
+use core::fmt;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction.
+///
+/// `x` and `y` operands are register numbers, `n` operands are 4-bit immediates and addresses
+/// are 12-bit, except for the 16-bit address of `LoadILong`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0x00E0: Erase display.
+    Clear,
+    /// 0x00EE: Return from subroutine.
+    Return,
+    /// 0x00CN: Scroll display N pixels down (SUPER-CHIP).
+    ScrollDown(u8),
+    /// 0x00DN: Scroll display N pixels up (XO-CHIP).
+    ScrollUp(u8),
+    /// 0x00FB: Scroll display 4 pixels right (SUPER-CHIP).
+    ScrollRight,
+    /// 0x00FC: Scroll display 4 pixels left (SUPER-CHIP).
+    ScrollLeft,
+    /// 0x00FD: Exit interpreter (SUPER-CHIP).
+    Exit,
+    /// 0x00FE: Switch to low resolution (SUPER-CHIP).
+    LowRes,
+    /// 0x00FF: Switch to high resolution (SUPER-CHIP).
+    HighRes,
+    /// 0x1MMM: Go to 0x0MMM.
+    Jump(u16),
+    /// 0x2MMM: Do subroutine at 0x0MMM.
+    Call(u16),
+    /// 0x3XKK: Skip next instruction if VX = KK.
+    SkipEqualByte(u8, u8),
+    /// 0x4XKK: Skip next instruction if VX != KK.
+    SkipNotEqualByte(u8, u8),
+    /// 0x5XY0: Skip next instruction if VX = VY.
+    SkipEqual(u8, u8),
+    /// 0x5XY2: Let MI = VX : VY (XO-CHIP).
+    SaveRange(u8, u8),
+    /// 0x5XY3: Let VX : VY = MI (XO-CHIP).
+    LoadRange(u8, u8),
+    /// 0x6XKK: Let VX = KK.
+    LoadByte(u8, u8),
+    /// 0x7XKK: Let VX = VX + KK.
+    AddByte(u8, u8),
+    /// 0x8XY0: Let VX = VY.
+    Load(u8, u8),
+    /// 0x8XY1: Let VX = VX | VY.
+    Or(u8, u8),
+    /// 0x8XY2: Let VX = VX & VY.
+    And(u8, u8),
+    /// 0x8XY3: Let VX = VX ^ VY.
+    Xor(u8, u8),
+    /// 0x8XY4: Let VX = VX + VY.
+    Add(u8, u8),
+    /// 0x8XY5: Let VX = VX - VY.
+    Sub(u8, u8),
+    /// 0x8XY6: Let VX = VY >> 1.
+    ShiftRight(u8, u8),
+    /// 0x8XY7: Let VX = VY - VX.
+    SubReverse(u8, u8),
+    /// 0x8XYE: Let VX = VY << 1.
+    ShiftLeft(u8, u8),
+    /// 0x9XY0: Skip next instruction if VX != VY.
+    SkipNotEqual(u8, u8),
+    /// 0xAMMM: Let I = 0x0MMM.
+    LoadI(u16),
+    /// 0xBMMM: Go to 0x0MMM + V0.
+    JumpOffset(u16),
+    /// 0xCXKK: Let VX = Random Byte & KK.
+    Random(u8, u8),
+    /// 0xDXYN: Show N byte MI pattern at VX-VY coordinates.
+    Draw(u8, u8, u8),
+    /// 0xEX9E: Skip next instruction if key VX is pressed.
+    SkipKey(u8),
+    /// 0xEXA1: Skip next instruction if key VX is not pressed.
+    SkipNotKey(u8),
+    /// 0xF000 0xMMMM: Let I = 0xMMMM (XO-CHIP).
+    LoadILong(u16),
+    /// 0xFN01: Select drawing planes N (XO-CHIP).
+    Plane(u8),
+    /// 0xF002: Let audio pattern = MI (XO-CHIP).
+    Audio,
+    /// 0xFX07: Let VX = delay timer.
+    LoadDelay(u8),
+    /// 0xFX0A: Let VX = next key pressed.
+    WaitKey(u8),
+    /// 0xFX15: Let delay timer = VX.
+    SetDelay(u8),
+    /// 0xFX18: Let sound timer = VX.
+    SetSound(u8),
+    /// 0xFX1E: Let I = I + VX.
+    AddI(u8),
+    /// 0xFX29: Let I = small font pattern for LSD of VX.
+    Font(u8),
+    /// 0xFX30: Let I = big font pattern for LSD of VX (SUPER-CHIP).
+    BigFont(u8),
+    /// 0xFX33: Let MI = 3 decimal digit equivalent of VX.
+    Bcd(u8),
+    /// 0xFX3A: Let audio pitch = VX (XO-CHIP).
+    Pitch(u8),
+    /// 0xFX55: Let MI = V0 : VX.
+    Store(u8),
+    /// 0xFX65: Let V0 : VX = MI.
+    Restore(u8),
+    /// 0xFX75: Let RPL flags = V0 : VX (SUPER-CHIP).
+    StoreFlags(u8),
+    /// 0xFX85: Let V0 : VX = RPL flags (SUPER-CHIP).
+    RestoreFlags(u8),
+}
+
+use self::Instruction::*;
+
+impl Instruction {
+    /// Decodes a single opcode word.
+    ///
+    /// Returns `None` for opcodes that are not instructions, including the 0xF000 prefix of
+    /// `LoadILong`, which needs the word that follows it; see `read`.
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        let mmm = opcode & 0xFFF;
+        let kk = (opcode & 0xFF) as u8;
+        let instruction = match nibbles(opcode) {
+            (0x0, 0x0, 0xE, 0x0) => Clear,
+            (0x0, 0x0, 0xE, 0xE) => Return,
+            (0x0, 0x0, 0xC, n) => ScrollDown(n),
+            (0x0, 0x0, 0xD, n) => ScrollUp(n),
+            (0x0, 0x0, 0xF, 0xB) => ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Exit,
+            (0x0, 0x0, 0xF, 0xE) => LowRes,
+            (0x0, 0x0, 0xF, 0xF) => HighRes,
+            (0x1, _, _, _) => Jump(mmm),
+            (0x2, _, _, _) => Call(mmm),
+            (0x3, x, _, _) => SkipEqualByte(x, kk),
+            (0x4, x, _, _) => SkipNotEqualByte(x, kk),
+            (0x5, x, y, 0x0) => SkipEqual(x, y),
+            (0x5, x, y, 0x2) => SaveRange(x, y),
+            (0x5, x, y, 0x3) => LoadRange(x, y),
+            (0x6, x, _, _) => LoadByte(x, kk),
+            (0x7, x, _, _) => AddByte(x, kk),
+            (0x8, x, y, 0x0) => Load(x, y),
+            (0x8, x, y, 0x1) => Or(x, y),
+            (0x8, x, y, 0x2) => And(x, y),
+            (0x8, x, y, 0x3) => Xor(x, y),
+            (0x8, x, y, 0x4) => Add(x, y),
+            (0x8, x, y, 0x5) => Sub(x, y),
+            (0x8, x, y, 0x6) => ShiftRight(x, y),
+            (0x8, x, y, 0x7) => SubReverse(x, y),
+            (0x8, x, y, 0xE) => ShiftLeft(x, y),
+            (0x9, x, y, 0x0) => SkipNotEqual(x, y),
+            (0xA, _, _, _) => LoadI(mmm),
+            (0xB, _, _, _) => JumpOffset(mmm),
+            (0xC, x, _, _) => Random(x, kk),
+            (0xD, x, y, n) => Draw(x, y, n),
+            (0xE, x, 0x9, 0xE) => SkipKey(x),
+            (0xE, x, 0xA, 0x1) => SkipNotKey(x),
+            (0xF, n, 0x0, 0x1) => Plane(n),
+            (0xF, 0x0, 0x0, 0x2) => Audio,
+            (0xF, x, 0x0, 0x7) => LoadDelay(x),
+            (0xF, x, 0x0, 0xA) => WaitKey(x),
+            (0xF, x, 0x1, 0x5) => SetDelay(x),
+            (0xF, x, 0x1, 0x8) => SetSound(x),
+            (0xF, x, 0x1, 0xE) => AddI(x),
+            (0xF, x, 0x2, 0x9) => Font(x),
+            (0xF, x, 0x3, 0x0) => BigFont(x),
+            (0xF, x, 0x3, 0x3) => Bcd(x),
+            (0xF, x, 0x3, 0xA) => Pitch(x),
+            (0xF, x, 0x5, 0x5) => Store(x),
+            (0xF, x, 0x6, 0x5) => Restore(x),
+            (0xF, x, 0x7, 0x5) => StoreFlags(x),
+            (0xF, x, 0x8, 0x5) => RestoreFlags(x),
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Decodes the instruction at the start of `bytes`, including the 4 byte `LoadILong`.
+    pub fn read(bytes: &[u8]) -> Option<Instruction> {
+        if bytes.len() < 2 {
+            return None;
+        }
+        let opcode = word(bytes[0], bytes[1]);
+        if opcode == 0xF000 {
+            if bytes.len() < 4 {
+                return None;
+            }
+            return Some(LoadILong(word(bytes[2], bytes[3])));
+        }
+        Self::decode(opcode)
+    }
+
+    /// The opcode word of this instruction (the first word of `LoadILong`).
+    pub fn encode(&self) -> u16 {
+        let xkk = |op: u16, x: u8, kk: u8| op << 12 | (x as u16) << 8 | kk as u16;
+        let xyn = |op: u16, x: u8, y: u8, n: u16| op << 12 | (x as u16) << 8 | (y as u16) << 4 | n;
+        match *self {
+            Clear => 0x00E0,
+            Return => 0x00EE,
+            ScrollDown(n) => 0x00C0 | n as u16,
+            ScrollUp(n) => 0x00D0 | n as u16,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowRes => 0x00FE,
+            HighRes => 0x00FF,
+            Jump(mmm) => 0x1000 | mmm,
+            Call(mmm) => 0x2000 | mmm,
+            SkipEqualByte(x, kk) => xkk(0x3, x, kk),
+            SkipNotEqualByte(x, kk) => xkk(0x4, x, kk),
+            SkipEqual(x, y) => xyn(0x5, x, y, 0x0),
+            SaveRange(x, y) => xyn(0x5, x, y, 0x2),
+            LoadRange(x, y) => xyn(0x5, x, y, 0x3),
+            LoadByte(x, kk) => xkk(0x6, x, kk),
+            AddByte(x, kk) => xkk(0x7, x, kk),
+            Load(x, y) => xyn(0x8, x, y, 0x0),
+            Or(x, y) => xyn(0x8, x, y, 0x1),
+            And(x, y) => xyn(0x8, x, y, 0x2),
+            Xor(x, y) => xyn(0x8, x, y, 0x3),
+            Add(x, y) => xyn(0x8, x, y, 0x4),
+            Sub(x, y) => xyn(0x8, x, y, 0x5),
+            ShiftRight(x, y) => xyn(0x8, x, y, 0x6),
+            SubReverse(x, y) => xyn(0x8, x, y, 0x7),
+            ShiftLeft(x, y) => xyn(0x8, x, y, 0xE),
+            SkipNotEqual(x, y) => xyn(0x9, x, y, 0x0),
+            LoadI(mmm) => 0xA000 | mmm,
+            JumpOffset(mmm) => 0xB000 | mmm,
+            Random(x, kk) => xkk(0xC, x, kk),
+            Draw(x, y, n) => xyn(0xD, x, y, n as u16),
+            SkipKey(x) => xkk(0xE, x, 0x9E),
+            SkipNotKey(x) => xkk(0xE, x, 0xA1),
+            LoadILong(_) => 0xF000,
+            Plane(n) => xkk(0xF, n, 0x01),
+            Audio => 0xF002,
+            LoadDelay(x) => xkk(0xF, x, 0x07),
+            WaitKey(x) => xkk(0xF, x, 0x0A),
+            SetDelay(x) => xkk(0xF, x, 0x15),
+            SetSound(x) => xkk(0xF, x, 0x18),
+            AddI(x) => xkk(0xF, x, 0x1E),
+            Font(x) => xkk(0xF, x, 0x29),
+            BigFont(x) => xkk(0xF, x, 0x30),
+            Bcd(x) => xkk(0xF, x, 0x33),
+            Pitch(x) => xkk(0xF, x, 0x3A),
+            Store(x) => xkk(0xF, x, 0x55),
+            Restore(x) => xkk(0xF, x, 0x65),
+            StoreFlags(x) => xkk(0xF, x, 0x75),
+            RestoreFlags(x) => xkk(0xF, x, 0x85),
+        }
+    }
+
+    /// All bytes of this instruction, big endian.
+    #[cfg(feature = "alloc")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let opcode = self.encode();
+        let mut bytes = vec![(opcode >> 8) as u8, opcode as u8];
+        if let LoadILong(mmmm) = *self {
+            bytes.push((mmmm >> 8) as u8);
+            bytes.push(mmmm as u8);
+        }
+        bytes
+    }
+
+    /// The length of this instruction in bytes: 4 for `LoadILong`, otherwise 2.
+    pub fn size(&self) -> usize {
+        match *self {
+            LoadILong(_) => 4,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Clear => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LowRes => write!(f, "LOW"),
+            HighRes => write!(f, "HIGH"),
+            Jump(mmm) => write!(f, "JP 0x{:03X}", mmm),
+            Call(mmm) => write!(f, "CALL 0x{:03X}", mmm),
+            SkipEqualByte(x, kk) => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            SkipNotEqualByte(x, kk) => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            SkipEqual(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            SaveRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LoadRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            LoadByte(x, kk) => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            AddByte(x, kk) => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            Load(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubReverse(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SkipNotEqual(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LoadI(mmm) => write!(f, "LD I, 0x{:03X}", mmm),
+            JumpOffset(mmm) => write!(f, "JP V0, 0x{:03X}", mmm),
+            Random(x, kk) => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey(x) => write!(f, "SKP V{:X}", x),
+            SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            LoadILong(mmmm) => write!(f, "LD I, LONG 0x{:04X}", mmmm),
+            Plane(n) => write!(f, "PLANE {}", n),
+            Audio => write!(f, "AUDIO"),
+            LoadDelay(x) => write!(f, "LD V{:X}, DT", x),
+            WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Font(x) => write!(f, "LD F, V{:X}", x),
+            BigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Store(x) => write!(f, "LD [I], V{:X}", x),
+            Restore(x) => write!(f, "LD V{:X}, [I]", x),
+            StoreFlags(x) => write!(f, "LD R, V{:X}", x),
+            RestoreFlags(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+fn nibbles(opcode: u16) -> (u8, u8, u8, u8) {
+    (
+        (opcode >> 12) as u8,
+        (opcode >> 8 & 0xF) as u8,
+        (opcode >> 4 & 0xF) as u8,
+        (opcode & 0xF) as u8,
+    )
+}
+
+fn word(high: u8, low: u8) -> u16 {
+    (high as u16) << 8 | low as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        assert!(Instruction::decode(0x00E0) == Some(Clear));
+        assert!(Instruction::decode(0x135F) == Some(Jump(0x35F)));
+        assert!(Instruction::decode(0x6342) == Some(LoadByte(0x3, 0x42)));
+        assert!(Instruction::decode(0xD455) == Some(Draw(0x4, 0x5, 5)));
+        assert!(Instruction::decode(0x8ABE) == Some(ShiftLeft(0xA, 0xB)));
+        assert!(Instruction::decode(0xF301) == Some(Plane(3)));
+        assert!(Instruction::decode(0x0000).is_none());
+        assert!(Instruction::decode(0x5AB1).is_none());
+        assert!(Instruction::decode(0xF000).is_none());
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for opcode in 0..=0xFFFF {
+            if let Some(instruction) = Instruction::decode(opcode) {
+                assert!(instruction.encode() == opcode);
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn to_bytes() {
+        for opcode in 0..=0xFFFF {
+            if let Some(instruction) = Instruction::decode(opcode) {
+                assert!(instruction.to_bytes() == vec![(opcode >> 8) as u8, opcode as u8]);
+            }
+        }
+        assert!(LoadILong(0xBEEF).to_bytes() == vec![0xF0, 0x00, 0xBE, 0xEF]);
+    }
+
+    #[test]
+    fn long_load() {
+        let instruction = Instruction::read(&[0xF0, 0x00, 0xBE, 0xEF]).unwrap();
+        assert!(instruction == LoadILong(0xBEEF));
+        assert!(instruction.size() == 4);
+        assert!(Instruction::read(&[0xF0, 0x00, 0xBE]).is_none());
+    }
+}
@@ -0,0 +1,1790 @@
+//! A CHIP-8, SUPER-CHIP and XO-CHIP interpreter.
+//!
+//! The crate is `no_std`. Without features it holds the machine itself; the `alloc` feature
+//! adds save states, movies, rewinding, headless runs, the debugger and the disassembler, and
+//! the default `std` feature adds audio, the GDB server and the assemblers.
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod access;
+#[cfg(feature = "std")]
+pub mod asm;
+#[cfg(feature = "std")]
+mod audio;
+mod bus;
+#[cfg(feature = "alloc")]
+mod debugger;
+#[cfg(feature = "alloc")]
+pub mod disasm;
+mod display;
+mod error;
+#[cfg(feature = "std")]
+mod gdb;
+#[cfg(feature = "alloc")]
+mod headless;
+mod instruction;
+#[cfg(feature = "alloc")]
+mod movie;
+#[cfg(feature = "std")]
+pub mod octo;
+mod quirks;
+mod random;
+#[cfg(feature = "alloc")]
+mod rewind;
+#[cfg(feature = "alloc")]
+pub mod screen;
+#[cfg(feature = "alloc")]
+mod state;
+pub use access::{Access, AccessKind};
+#[cfg(feature = "std")]
+pub use audio::{Audio, AudioSink};
+pub use bus::{Bus, Hooked, Ram, MEMORY_SIZE};
+#[cfg(feature = "alloc")]
+pub use debugger::{Command, Debugger, Stop, Watchpoint};
+pub use display::{Display, Framebuffer, Rect};
+pub use error::Chip8Error;
+#[cfg(feature = "std")]
+pub use gdb::{GdbServer, GdbState};
+#[cfg(feature = "alloc")]
+pub use headless::{Headless, Script, ScriptError, Summary, Until};
+pub use instruction::Instruction;
+#[cfg(feature = "alloc")]
+pub use movie::{KeyEvent, Movie, MovieError, Player, Recorder};
+pub use quirks::{Increment, Quirks};
+pub use random::{Random, RandomSource};
+#[cfg(feature = "alloc")]
+pub use rewind::Rewind;
+#[cfg(feature = "alloc")]
+pub use state::StateError;
+
+/// The hex keypad of the COSMAC VIP, row by row. Frontends lay it over the four keyboard rows
+/// starting with 1, Q, A and Z, so keys sit where they did on the original.
+pub const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+pub struct Chip8<D: Display = Framebuffer, B: Bus = Ram> {
+    pub i: usize,
+    pub pc: usize,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack: [usize; 32],
+    pub registers: [u8; 16],
+    pub bus: B,
+    pub display: D,
+    pub hires: bool,
+    pub planes: u8,
+    pub rpl: [u8; 16],
+    pub keys: [bool; 16],
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    pub needs_redraw: bool,
+    pub needs_input: bool,
+    pub exited: bool,
+    pub quirks: Quirks,
+    pub random: Random,
+    input_register: usize,
+    last_access: Option<Access>,
+}
+
+impl Chip8 {
+    pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        Self::with_display(quirks, Framebuffer::new())
+    }
+
+    /// Creates a machine whose random numbers come from a xorshift generator seeded with
+    /// `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut chip8 = Self::new();
+        chip8.random = Random::new(RandomSource::Xorshift, seed);
+        chip8
+    }
+}
+
+impl<D: Display> Chip8<D> {
+    /// Creates a machine that draws on `display`.
+    pub fn with_display(quirks: Quirks, display: D) -> Self {
+        Self::with_display_and_bus(quirks, display, Ram::new())
+    }
+}
+
+impl<D: Display, B: Bus> Chip8<D, B> {
+    /// Creates a machine that draws on `display` and runs from the memory behind `bus`.
+    pub fn with_display_and_bus(quirks: Quirks, mut display: D, bus: B) -> Self {
+        display.resize(64, 32);
+        let mut chip8 = Chip8 {
+            i: 0,
+            pc: 0x200,
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            registers: [0; 16],
+            stack: [0; 32],
+            bus,
+            display,
+            hires: false,
+            planes: 1,
+            rpl: [0; 16],
+            keys: [false; 16],
+            audio_pattern: None,
+            pitch: 64,
+            needs_redraw: true,
+            needs_input: false,
+            exited: false,
+            quirks,
+            random: Random::default(),
+            input_register: 0,
+            last_access: None,
+        };
+
+        // Initalize fonts at the start of system memory, followed by the large SUPER-CHIP fonts.
+        for (address, &byte) in FONTS.iter().enumerate() {
+            chip8.bus.poke(address, byte);
+        }
+        for (offset, &byte) in BIG_FONTS.iter().enumerate() {
+            chip8.bus.poke(BIG_FONTS_START + offset, byte);
+        }
+
+        chip8
+    }
+
+    /// Resets the machine and copies the program to 0x200, unless it does not fit in memory.
+    pub fn load(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
+        if program.len() > MEMORY_SIZE - 0x200 {
+            return Err(Chip8Error::ProgramTooLarge {
+                pc: 0x200,
+                size: program.len(),
+            });
+        }
+        self.i = 0;
+        self.pc = 0x200;
+        self.sp = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.needs_redraw = true;
+        self.needs_input = false;
+        self.exited = false;
+        self.last_access = None;
+        self.hires = false;
+        self.planes = 1;
+        self.audio_pattern = None;
+        self.pitch = 64;
+        self.input_register = 0;
+        self.random.reset();
+        for i in 0..self.registers.len() {
+            self.registers[i] = 0;
+        }
+        for i in 0..self.stack.len() {
+            self.stack[i] = 0;
+        }
+        for (offset, &byte) in program.iter().enumerate() {
+            self.bus.poke(self.pc + offset, byte);
+        }
+        self.display.resize(64, 32);
+        for i in 0..self.keys.len() {
+            self.keys[i] = false;
+        }
+        Ok(())
+    }
+
+    pub fn cycle(&mut self) -> Result<(), Chip8Error> {
+        self.last_access = None;
+        if !self.needs_input && !self.exited {
+            self.execute_op()?;
+        }
+
+        Ok(())
+    }
+
+    /// Decrements the delay and sound timers. Call this at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    /// Runs one 1/60 second frame: `cycles_per_frame` instructions followed by a timer tick.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Result<(), Chip8Error> {
+        let mut redraw = false;
+        for _ in 0..cycles_per_frame {
+            if let Err(err) = self.cycle() {
+                self.needs_redraw |= redraw;
+                return Err(err);
+            }
+            redraw |= self.needs_redraw;
+        }
+        self.needs_redraw = redraw;
+        self.tick_timers();
+
+        Ok(())
+    }
+
+    /// Decodes the instruction at an address, without any side effects on the bus.
+    pub fn instruction_at(&self, address: usize) -> Option<Instruction> {
+        let mut bytes = [0; 4];
+        let len = MEMORY_SIZE.saturating_sub(address).min(bytes.len());
+        for (offset, byte) in bytes[..len].iter_mut().enumerate() {
+            *byte = self.bus.peek(address + offset);
+        }
+        Instruction::read(&bytes[..len])
+    }
+
+    /// The memory read or written as data by the last executed instruction, if any.
+    pub fn last_access(&self) -> Option<Access> {
+        self.last_access
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            128
+        } else {
+            64
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            64
+        } else {
+            32
+        }
+    }
+
+    pub fn key_down(&mut self, keycode: u8) {
+        self.keys[keycode as usize] = true;
+        if self.needs_input {
+            self.registers[self.input_register] = keycode;
+            self.next();
+        }
+        self.needs_input = false;
+    }
+
+    pub fn key_up(&mut self, keycode: u8) {
+        self.keys[keycode as usize] = false;
+        self.needs_input = false;
+    }
+
+    fn execute_op(&mut self) -> Result<(), Chip8Error> {
+        if self.pc + 1 >= MEMORY_SIZE {
+            return Err(Chip8Error::PcOutOfBounds { pc: self.pc });
+        }
+
+        self.needs_redraw = false;
+
+        match self.fetch_op()? {
+            Instruction::Jump(mmm) => {
+                // 0x1MMM: Go to 0x0MMM
+                self.go_to(mmm as usize);
+            }
+            Instruction::JumpOffset(mmm) => {
+                // 0xBMMM: Go to 0x0MMM + V0 (0x0MMM + VA with the jump_vx quirk)
+                let mmm = mmm as usize;
+                let offset = if self.quirks.jump_vx { mmm >> 8 } else { 0 };
+                let v = self.registers[offset] as usize;
+                self.go_to(mmm + v);
+            }
+            Instruction::Call(mmm) => {
+                // 0x2MMM: Do subroutine at 0x0MMM (must end with 0x00EE)
+                let mmm = mmm as usize;
+                if self.sp >= self.stack.len() {
+                    return Err(Chip8Error::StackOverflow {
+                        pc: self.pc,
+                        opcode: self.opcode(),
+                    });
+                }
+                self.stack[self.sp] = self.pc;
+                self.sp += 1;
+                self.go_to(mmm);
+            }
+            Instruction::Return => {
+                // 0x00EE: Return from subroutine
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow {
+                        pc: self.pc,
+                        opcode: self.opcode(),
+                    });
+                }
+                self.sp -= 1;
+                let return_address = self.stack[self.sp];
+                self.go_to(return_address);
+                self.next();
+            }
+            Instruction::SkipEqualByte(x, kk) => {
+                // 0x3XKK: Skip next instruction if VX = KK
+                let vx = self.registers[x as usize];
+                self.skip_if(vx == kk);
+            }
+            Instruction::SkipNotEqualByte(x, kk) => {
+                // 0x4XKK: Skip next instruction if VX != KK
+                let vx = self.registers[x as usize];
+                self.skip_if(vx != kk);
+            }
+            Instruction::SkipEqual(x, y) => {
+                // 0x5XY0: Skip next instruction if VX = VY
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                self.skip_if(vx == vy);
+            }
+            Instruction::SaveRange(x, y) => {
+                // 0x5XY2: Let MI = VX : VY, in either order (I unchanged) (XO-CHIP)
+                let registers = register_range(x, y);
+                self.access(AccessKind::Write, self.i, registers.len())?;
+                for (offset, r) in registers.enumerate() {
+                    self.bus.write(self.i + offset, self.registers[r]);
+                }
+                self.next();
+            }
+            Instruction::LoadRange(x, y) => {
+                // 0x5XY3: Let VX : VY = MI, in either order (I unchanged) (XO-CHIP)
+                let registers = register_range(x, y);
+                self.access(AccessKind::Read, self.i, registers.len())?;
+                for (offset, r) in registers.enumerate() {
+                    self.registers[r] = self.bus.read(self.i + offset);
+                }
+                self.next();
+            }
+            Instruction::SkipNotEqual(x, y) => {
+                // 0x9XY0: Skip next instruction if VX != VY
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                self.skip_if(vx != vy);
+            }
+            Instruction::LoadByte(x, kk) => {
+                // 0x6XKK: Let VX = KK
+                self.registers[x as usize] = kk;
+                self.next();
+            }
+            Instruction::AddByte(x, kk) => {
+                // 0x7XKK: Let VX = VX + KK
+                let vx = self.registers[x as usize];
+                self.registers[x as usize] = vx.wrapping_add(kk);
+                self.next();
+            }
+            Instruction::Load(x, y) => {
+                // 0x8XY0: Let VX = VY
+                let vy = self.registers[y as usize];
+                self.registers[x as usize] = vy;
+                self.next();
+            }
+            Instruction::Or(x, y) => {
+                // 0x8XY1: Let VX = VX | VY (VF = 0x00 with the reset_vf quirk)
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                self.registers[x as usize] = vx | vy;
+                if self.quirks.reset_vf {
+                    self.registers[0xF] = 0;
+                }
+                self.next();
+            }
+            Instruction::And(x, y) => {
+                // 0x8XY2: Let VX = VX & VY (VF = 0x00 with the reset_vf quirk)
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                self.registers[x as usize] = vx & vy;
+                if self.quirks.reset_vf {
+                    self.registers[0xF] = 0;
+                }
+                self.next();
+            }
+            Instruction::Xor(x, y) => {
+                // 0x8XY3: Let VX = VX ^ VY (VF = 0x00 with the reset_vf quirk)
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                self.registers[x as usize] = vx ^ vy;
+                if self.quirks.reset_vf {
+                    self.registers[0xF] = 0;
+                }
+                self.next();
+            }
+            Instruction::Add(x, y) => {
+                // 0x8XY4: Let VX = VX + VY (VF = 0x00 if VX + VY <= 0xFF, VF = 0x01 if VX + VY > 0xFF)
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                let r = vx.wrapping_add(vy);
+                self.registers[x as usize] = r;
+                self.registers[0xF] = if r < vx { 1 } else { 0 };
+                self.next();
+            }
+            Instruction::Sub(x, y) => {
+                // 0x8XY5: Let VX = VX - VY (VF = 0x00 if VX < VY, VF = 0x01 if VX >= VY)
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                let r = vx.wrapping_sub(vy);
+                self.registers[x as usize] = r;
+                self.registers[0xF] = if vx < vy { 0 } else { 1 };
+                self.next();
+            }
+            Instruction::ShiftRight(x, y) => {
+                // 0x8XY6: Let VX = VY >> 1 (VF = lsb prior to shift, VY = VX with the shift_vx quirk)
+                let source = if self.quirks.shift_vx { x } else { y };
+                let v = self.registers[source as usize];
+                let r = v >> 1;
+                self.registers[x as usize] = r;
+                self.registers[0xF] = v & 1;
+                self.next();
+            }
+            Instruction::SubReverse(x, y) => {
+                // 0x8XY7: Let VX = VY - VX (VF = 0x00 if VY < VX, VF = 0x01 if VY >= VX)
+                let vx = self.registers[x as usize];
+                let vy = self.registers[y as usize];
+                let r = vy.wrapping_sub(vx);
+                self.registers[x as usize] = r;
+                self.registers[0xF] = if vy < vx { 0 } else { 1 };
+                self.next();
+            }
+            Instruction::ShiftLeft(x, y) => {
+                // 0x8XYE: Let VX = VY << 1 (VF = msb prior to shift, VY = VX with the shift_vx quirk)
+                let source = if self.quirks.shift_vx { x } else { y };
+                let v = self.registers[source as usize];
+                let r = v << 1;
+                self.registers[x as usize] = r;
+                self.registers[0xF] = v >> 7;
+                self.next();
+            }
+            Instruction::Random(x, kk) => {
+                // 0xCXKK: Let VX = Random Byte (KK = Mask)
+                let rb = self.random.next_byte();
+                self.registers[x as usize] = rb & kk;
+                self.next();
+            }
+            Instruction::LoadI(mmm) => {
+                // 0xAMMM: Let I = 0x0MMM
+                self.i = mmm as usize;
+                self.next();
+            }
+            Instruction::LoadILong(mmmm) => {
+                // 0xF000 0xMMMM: Let I = 0xMMMM (XO-CHIP)
+                self.i = mmmm as usize;
+                self.pc += 4;
+            }
+            Instruction::Plane(n) => {
+                // 0xFN01: Select drawing planes N (XO-CHIP)
+                self.planes = n & 0x3;
+                self.next();
+            }
+            Instruction::Audio => {
+                // 0xF002: Let audio pattern = 16 byte MI pattern (XO-CHIP)
+                self.access(AccessKind::Read, self.i, 16)?;
+                let mut pattern = [0; 16];
+                for (offset, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.bus.read(self.i + offset);
+                }
+                self.audio_pattern = Some(pattern);
+                self.next();
+            }
+            Instruction::Pitch(x) => {
+                // 0xFX3A: Let audio pattern pitch = VX (XO-CHIP)
+                self.pitch = self.registers[x as usize];
+                self.next();
+            }
+            Instruction::AddI(x) => {
+                // 0xFX1E: Let I = I + VX
+                let vx = self.registers[x as usize];
+                self.i += vx as usize;
+                self.next();
+            }
+            Instruction::Font(x) => {
+                // 0xFX29: Let I = 5 byte display pattern for LSD of VX
+                let vx = self.registers[x as usize];
+                let lsd = vx & 0xF;
+                self.i = 5 * lsd as usize;
+                self.next();
+            }
+            Instruction::BigFont(x) => {
+                // 0xFX30: Let I = 10 byte display pattern for LSD of VX (SUPER-CHIP)
+                let vx = self.registers[x as usize];
+                let lsd = vx & 0xF;
+                self.i = BIG_FONTS_START + 10 * lsd as usize;
+                self.next();
+            }
+            Instruction::StoreFlags(x) => {
+                // 0xFX75: Let RPL flags = V0 : VX (SUPER-CHIP)
+                let n = x as usize + 1;
+                self.rpl[..n].copy_from_slice(&self.registers[..n]);
+                self.next();
+            }
+            Instruction::RestoreFlags(x) => {
+                // 0xFX85: Let V0 : VX = RPL flags (SUPER-CHIP)
+                let n = x as usize + 1;
+                self.registers[..n].copy_from_slice(&self.rpl[..n]);
+                self.next();
+            }
+            Instruction::Bcd(x) => {
+                // 0xFX33: Let MI = 3 decimal digit equivalent of VX (I unchanged)
+                self.access(AccessKind::Write, self.i, 3)?;
+                let vx = self.registers[x as usize];
+                self.bus.write(self.i, vx / 100);
+                self.bus.write(self.i + 1, vx / 10 % 10);
+                self.bus.write(self.i + 2, vx % 10);
+                self.next();
+            }
+            Instruction::Store(x) => {
+                // 0xFX55: Let MI = V0 : VX (I moved by the increment_i quirk)
+                self.access(AccessKind::Write, self.i, x as usize + 1)?;
+                for i in 0..((x + 1) as usize) {
+                    let vx = self.registers[i];
+                    self.bus.write(self.i + i, vx);
+                }
+                self.i += self.quirks.increment_i.amount(x);
+                self.next();
+            }
+            Instruction::Restore(x) => {
+                // 0xFX65: Let V0 : VX = MI (I moved by the increment_i quirk)
+                self.access(AccessKind::Read, self.i, x as usize + 1)?;
+                for i in 0..((x + 1) as usize) {
+                    let mx = self.bus.read(self.i + i);
+                    self.registers[i] = mx;
+                }
+                self.i += self.quirks.increment_i.amount(x);
+                self.next();
+            }
+            Instruction::Clear => {
+                // 0x00E0: Erase display (all 0s in the selected planes)
+                self.needs_redraw = true;
+                self.display.clear(self.planes);
+                self.next();
+            }
+            Instruction::ScrollDown(n) => {
+                // 0x00CN: Scroll display N pixels down (SUPER-CHIP)
+                self.scroll(0, n as isize);
+                self.next();
+            }
+            Instruction::ScrollUp(n) => {
+                // 0x00DN: Scroll display N pixels up (XO-CHIP)
+                self.scroll(0, -(n as isize));
+                self.next();
+            }
+            Instruction::ScrollRight => {
+                // 0x00FB: Scroll display 4 pixels right (SUPER-CHIP)
+                self.scroll(4, 0);
+                self.next();
+            }
+            Instruction::ScrollLeft => {
+                // 0x00FC: Scroll display 4 pixels left (SUPER-CHIP)
+                self.scroll(-4, 0);
+                self.next();
+            }
+            Instruction::Exit => {
+                // 0x00FD: Exit interpreter (SUPER-CHIP)
+                self.exited = true;
+            }
+            Instruction::LowRes => {
+                // 0x00FE: Switch to 64x32 low resolution and erase display (SUPER-CHIP)
+                self.set_hires(false);
+                self.next();
+            }
+            Instruction::HighRes => {
+                // 0x00FF: Switch to 128x64 high resolution and erase display (SUPER-CHIP)
+                self.set_hires(true);
+                self.next();
+            }
+            Instruction::Draw(x, y, n) => {
+                // 0xDXYN: Show n byte MI pattern at VX-VY coordinates. I unchanged. MI pattern is
+                // combined with existing display via EXCLUSIVE-OR function. VF = 0x01 if a 1 in MI
+                // pattern matches 1 in existing display. Pixels past the edge, starting ones
+                // included, are clipped, or wrapped with the wrap_sprites quirk.
+                // 0xDXY0: Show 32 byte MI pattern as a 16x16 sprite (SUPER-CHIP).
+                // With both planes selected, the pattern for plane 2 follows the one for plane 1
+                // (XO-CHIP).
+                let (rows, columns) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+                let size = rows * columns / 8;
+                let selected = self.planes;
+                let planes = [1, 2]
+                    .iter()
+                    .cloned()
+                    .filter(move |plane| selected & plane != 0);
+                self.access(AccessKind::Read, self.i, size * planes.clone().count())?;
+                self.needs_redraw = true;
+                self.registers[0xF] = 0x0;
+                let vx = self.registers[x as usize] as usize;
+                let vy = self.registers[y as usize] as usize;
+                for (p, plane) in planes.enumerate() {
+                    let mut sprite = [0; 32];
+                    for (offset, byte) in sprite[..size].iter_mut().enumerate() {
+                        *byte = self.bus.read(self.i + size * p + offset);
+                    }
+                    let wrap = self.quirks.wrap_sprites;
+                    if self
+                        .display
+                        .xor_sprite(vx, vy, &sprite[..size], columns, plane, wrap)
+                    {
+                        self.registers[0xF] = 0x1;
+                    }
+                }
+                self.next();
+            }
+            Instruction::LoadDelay(x) => {
+                // 0xFX07: Let VX = current timer value
+                self.registers[x as usize] = self.delay_timer;
+                self.next();
+            }
+            Instruction::SetDelay(x) => {
+                // 0xFX15: Set timer = VX (0x01 = 1/60 second)
+                let vx = self.registers[x as usize];
+                self.delay_timer = vx;
+                self.next();
+            }
+            Instruction::SetSound(x) => {
+                // 0xFX18: Set tone duration = VX (0x01 = 1/60 second)
+                let vx = self.registers[x as usize];
+                self.sound_timer = vx;
+                self.next();
+            }
+            Instruction::SkipKey(x) => {
+                // 0xEX9E: Skip next instruction if VX = hexadecimal key (LSD)
+                let vx = self.registers[x as usize];
+                let lsd = vx & 0xF;
+                let key_pressed = self.keys[lsd as usize];
+                self.skip_if(key_pressed);
+            }
+            Instruction::SkipNotKey(x) => {
+                // 0xEXA1: Skip next instruction if VX != hexadecimal key (LSD)
+                let vx = self.registers[x as usize];
+                let lsd = vx & 0xF;
+                let key_pressed = self.keys[lsd as usize];
+                self.skip_if(!key_pressed);
+            }
+            Instruction::WaitKey(x) => {
+                // 0xFX0A: Let VX = hexadecimal key digit (waits for any key pressed)
+                self.needs_input = true;
+                self.input_register = x as usize;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fetch_op(&self) -> Result<Instruction, Chip8Error> {
+        let opcode = self.opcode();
+        if opcode == 0xF000 {
+            // The address of the 0xF000 0xMMMM long load follows the opcode (XO-CHIP).
+            self.check_memory(self.pc + 2, 2)?;
+            return Ok(self.instruction_at(self.pc).unwrap());
+        }
+        // TODO
+        // 0x0MMM: Do machine language at 0x0MMM (subroutine must end with 0xD4 byte)
+        Instruction::decode(opcode).ok_or(Chip8Error::UnknownOpcode {
+            pc: self.pc,
+            opcode,
+        })
+    }
+
+    fn opcode(&self) -> u16 {
+        ((self.bus.peek(self.pc) as u16) << 8) | self.bus.peek(self.pc + 1) as u16
+    }
+
+    fn check_memory(&self, address: usize, len: usize) -> Result<(), Chip8Error> {
+        if address + len > MEMORY_SIZE {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                pc: self.pc,
+                opcode: self.opcode(),
+                address: address.max(MEMORY_SIZE),
+            });
+        }
+        Ok(())
+    }
+
+    // Checks and records a data access of the current instruction.
+    fn access(&mut self, kind: AccessKind, address: usize, len: usize) -> Result<(), Chip8Error> {
+        self.check_memory(address, len)?;
+        if kind == AccessKind::Write {
+            if let Some(address) = (address..address + len).find(|&a| !self.bus.writable(a)) {
+                return Err(Chip8Error::ReadOnly {
+                    pc: self.pc,
+                    opcode: self.opcode(),
+                    address,
+                });
+            }
+        }
+        self.last_access = Some(Access { kind, address, len });
+        Ok(())
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.needs_redraw = true;
+        self.display.resize(self.width(), self.height());
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        self.display.scroll(dx, dy, self.planes);
+        self.needs_redraw = true;
+    }
+
+    fn go_to(&mut self, address: usize) {
+        self.pc = address;
+    }
+
+    fn next(&mut self) {
+        self.pc += 2;
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            // The 4 byte 0xF000 0xMMMM instruction is skipped as a whole (XO-CHIP).
+            let next = self
+                .instruction_at(self.pc + 2)
+                .map_or(2, |instruction| instruction.size());
+            self.pc += 2 + next;
+        } else {
+            self.pc += 2;
+        }
+    }
+}
+
+fn register_range(x: u8, y: u8) -> impl ExactSizeIterator<Item = usize> {
+    let (x, y) = (x as usize, y as usize);
+    let len = x.max(y) - x.min(y) + 1;
+    (0..len).map(move |offset| if x <= y { x + offset } else { x - offset })
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static FONTS: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80  // F
+];
+
+const BIG_FONTS_START: usize = 0x50;
+
+static BIG_FONTS: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[cfg(test)]
+#[allow(clippy::identity_op)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_chip8() {
+        let chip8 = Chip8::new();
+        assert!(chip8.bus.bytes.len() == 0x10000);
+        assert!(chip8.stack.len() == 32);
+        assert!(chip8.registers.len() == 16);
+        assert!(chip8.pc == 0x200);
+        for i in 0..FONTS.len() {
+            assert!(chip8.bus.bytes[i] != 0);
+        }
+    }
+
+    #[test]
+    fn load_too_large() {
+        let mut chip8 = Chip8::new();
+        let program = [0x12; MEMORY_SIZE - 0x1FF];
+        chip8.pc = 0x300;
+        assert!(
+            chip8.load(&program)
+                == Err(Chip8Error::ProgramTooLarge {
+                    pc: 0x200,
+                    size: 0xFE01
+                })
+        );
+        assert!(chip8.pc == 0x300);
+        assert!(chip8.bus.bytes[0x200] == 0);
+
+        chip8.load(&program[1..]).unwrap();
+        assert!(chip8.pc == 0x200);
+        assert!(chip8.bus.bytes[0xFFFF] == 0x12);
+    }
+
+    #[test]
+    fn fetch_op() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF0;
+        chip8.bus.bytes[0x201] = 0x00;
+        chip8.bus.bytes[0x202] = 0xD3;
+        chip8.bus.bytes[0x203] = 0x40;
+        assert!(chip8.fetch_op() == Ok(Instruction::LoadILong(0xD340)));
+        chip8.pc += 2;
+        assert!(chip8.fetch_op() == Ok(Instruction::Draw(0x3, 0x4, 0x0)));
+    }
+
+    #[test]
+    fn op_1mmm() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x13;
+        chip8.bus.bytes[0x201] = 0x5F;
+        chip8.bus.bytes[0x35F] = 0x12;
+        chip8.bus.bytes[0x35F + 1] = 0x00;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x35F);
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x200);
+    }
+
+    #[test]
+    fn op_bmmm() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xB3;
+        chip8.bus.bytes[0x201] = 0x00;
+        chip8.registers[0] = 0xF0;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x300 + 0xF0);
+    }
+
+    #[test]
+    fn op_3xkk() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x33;
+        chip8.bus.bytes[0x201] = 0x42;
+        chip8.registers[3] = 0x41;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x202);
+        chip8.pc = 0x200;
+        chip8.registers[3] = 0x42;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x204);
+    }
+
+    #[test]
+    fn op_4xkk() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x4F;
+        chip8.bus.bytes[0x201] = 0xF0;
+        chip8.registers[0xF] = 0xF0;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x202);
+        chip8.pc = 0x200;
+        chip8.registers[0xF] = 0x42;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x204);
+    }
+
+    #[test]
+    fn op_5xy0() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x50;
+        chip8.bus.bytes[0x201] = 0xB0;
+        chip8.registers[0] = 0x33;
+        chip8.registers[0xB] = 0x23;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x202);
+        chip8.pc = 0x200;
+        chip8.registers[0xB] = 0x33;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x204);
+    }
+
+    #[test]
+    fn op_9xy0() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x9C;
+        chip8.bus.bytes[0x201] = 0xA0;
+        chip8.registers[0xC] = 0xFF;
+        chip8.registers[0xA] = 0xEE;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x204);
+        chip8.pc = 0x200;
+        chip8.registers[0xA] = 0xFF;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x202);
+    }
+
+    #[test]
+    fn op_6xkk() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x68;
+        chip8.bus.bytes[0x201] = 0x42;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x8] == 0x42);
+    }
+
+    #[test]
+    fn op_7xkk() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x7A;
+        chip8.bus.bytes[0x201] = 0x10;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0xA] == 0x10);
+
+        // Overflow should wrap around.
+        chip8.pc = 0x200;
+        chip8.bus.bytes[0x201] = 0xFF;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0xA] == 0x10 - 1);
+    }
+
+    #[test]
+    fn op_8xy0() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x8A;
+        chip8.bus.bytes[0x201] = 0xB0;
+        chip8.registers[0xB] = 0xF0;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0xA] == 0xF0);
+        assert!(chip8.registers[0xB] == 0xF0);
+    }
+
+    #[test]
+    fn op_8xy1() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x41;
+        chip8.registers[0x3] = 0x39;
+        chip8.registers[0x4] = 0xCD;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x39 | 0xCD);
+    }
+
+    #[test]
+    fn op_8xy2() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x42;
+        chip8.registers[0x3] = 0x39;
+        chip8.registers[0x4] = 0xCD;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x39 & 0xCD);
+    }
+
+    #[test]
+    fn op_8xy4() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x44;
+        chip8.registers[0x3] = 0x39;
+        chip8.registers[0x4] = 0x0D;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x39 + 0x0D);
+        assert!(chip8.registers[0xF] == 0);
+
+        // Overflow should wrap around.
+        chip8.pc = 0x200;
+        chip8.registers[0x4] = 0xFF;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == (0x39 + 0x0D) - 1);
+        assert!(chip8.registers[0xF] == 1);
+    }
+
+    #[test]
+    fn op_8xy5() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x45;
+        chip8.registers[0x3] = 0x39;
+        chip8.registers[0x4] = 0x0D;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x39 - 0x0D);
+        assert!(chip8.registers[0xF] == 1);
+
+        // Overflow should wrap around.
+        chip8.pc = 0x200;
+        chip8.registers[0x4] = 0xFF;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == (0x39 - 0x0D) + 1);
+        assert!(chip8.registers[0xF] == 0);
+    }
+
+    #[test]
+    fn op_8xy6() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x46;
+        chip8.registers[0x4] = 0x0D;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x06);
+        assert!(chip8.registers[0xF] == 1);
+
+        chip8.pc = 0x200;
+        chip8.registers[0x4] = 0x0C;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x06);
+        assert!(chip8.registers[0xF] == 0);
+    }
+
+    #[test]
+    fn op_8xy7() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x47;
+        chip8.registers[0x3] = 0x0D;
+        chip8.registers[0x4] = 0x39;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x39 - 0x0D);
+        assert!(chip8.registers[0xF] == 1);
+
+        // Overflow should wrap around.
+        chip8.pc = 0x200;
+        chip8.registers[0x4] = 0x00;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0xD4);
+        assert!(chip8.registers[0xF] == 0);
+
+        // VF is written after the result when X is 0xF.
+        chip8.pc = 0x200;
+        chip8.bus.bytes[0x200] = 0x8F;
+        chip8.registers[0xF] = 0x10;
+        chip8.registers[0x4] = 0x08;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0xF] == 0);
+    }
+
+    #[test]
+    fn op_8xye() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x4E;
+        chip8.registers[0x4] = 0x41;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x82);
+        assert!(chip8.registers[0xF] == 0);
+
+        // The most significant bit is shifted out into VF.
+        chip8.pc = 0x200;
+        chip8.registers[0x4] = 0xC1;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x82);
+        assert!(chip8.registers[0xF] == 1);
+
+        // VF is written after the result when X is 0xF.
+        chip8.pc = 0x200;
+        chip8.bus.bytes[0x200] = 0x8F;
+        chip8.registers[0x4] = 0x01;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0xF] == 0);
+    }
+
+    #[test]
+    fn op_ammm() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xA9;
+        chip8.bus.bytes[0x201] = 0x08;
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x908);
+    }
+
+    #[test]
+    fn op_fx1e() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF4;
+        chip8.bus.bytes[0x201] = 0x1E;
+        chip8.i = 0x500;
+        chip8.registers[4] = 0x20;
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x500 + 0x20);
+    }
+
+    #[test]
+    fn op_fx29() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xFC;
+        chip8.bus.bytes[0x201] = 0x29;
+        chip8.registers[0xC] = 0x0A;
+        chip8.cycle().unwrap();
+        for i in 0..5 {
+            assert!(chip8.bus.bytes[chip8.i + i] == FONTS[(5 * 0xA) + i]);
+        }
+        chip8.pc = 0x200;
+        chip8.bus.bytes[0x200] = 0xFC;
+        chip8.bus.bytes[0x201] = 0x29;
+        chip8.registers[0xC] = 0xD1;
+        chip8.cycle().unwrap();
+        for i in 0..5 {
+            assert!(chip8.bus.bytes[chip8.i + i] == FONTS[(5 * 0x1) + i]);
+        }
+    }
+
+    #[test]
+    fn op_fx33() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF1;
+        chip8.bus.bytes[0x201] = 0x33;
+        chip8.registers[1] = 243;
+        chip8.i = 0x500;
+        chip8.cycle().unwrap();
+        assert!(chip8.bus.bytes[chip8.i + 0] == 2);
+        assert!(chip8.bus.bytes[chip8.i + 1] == 4);
+        assert!(chip8.bus.bytes[chip8.i + 2] == 3);
+        chip8.pc = 0x200;
+        chip8.registers[1] = 91;
+        chip8.cycle().unwrap();
+        assert!(chip8.bus.bytes[chip8.i + 0] == 0);
+        assert!(chip8.bus.bytes[chip8.i + 1] == 9);
+        assert!(chip8.bus.bytes[chip8.i + 2] == 1);
+        chip8.pc = 0x200;
+        chip8.registers[1] = 5;
+        chip8.cycle().unwrap();
+        assert!(chip8.bus.bytes[chip8.i + 0] == 0);
+        assert!(chip8.bus.bytes[chip8.i + 1] == 0);
+        assert!(chip8.bus.bytes[chip8.i + 2] == 5);
+    }
+
+    #[test]
+    fn op_fx55() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF7;
+        chip8.bus.bytes[0x201] = 0x55;
+        for i in 0..8 {
+            chip8.registers[i] = 200 + (i as u8);
+        }
+        chip8.i = 0x450;
+        chip8.cycle().unwrap();
+        for i in 0..8 {
+            assert!(chip8.bus.bytes[0x450 + i] == 200 + (i as u8));
+        }
+    }
+
+    #[test]
+    fn op_fx65() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xFE;
+        chip8.bus.bytes[0x201] = 0x65;
+        chip8.i = 0x600;
+        for i in 0..0xF {
+            chip8.bus.bytes[chip8.i + i] = 33 + (4 * i as u8);
+        }
+        chip8.cycle().unwrap();
+        for i in 0..8 {
+            assert!(chip8.registers[i] == 33 + (4 * i as u8));
+        }
+    }
+
+    #[test]
+    fn op_00e0() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0xE0;
+        for i in 0..chip8.display.pixels.len() {
+            chip8.display.pixels[i] = 1;
+        }
+        chip8.cycle().unwrap();
+        for i in 0..chip8.display.pixels.len() {
+            assert!(chip8.display.pixels[i] == 0);
+        }
+        assert!(chip8.needs_redraw);
+    }
+
+    #[test]
+    fn op_dxyn() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xD4;
+        chip8.bus.bytes[0x201] = 0x55;
+        chip8.registers[4] = 10;
+        chip8.registers[5] = 12;
+        chip8.i = 0x500;
+        for i in 0..5 {
+            chip8.bus.bytes[chip8.i + i] = 0xFF;
+        }
+        chip8.cycle().unwrap();
+        for x in 0..8 {
+            for y in 0..5 {
+                assert!(chip8.display.pixels[64 * (12 + y) + (10 + x)] == 1);
+            }
+        }
+        assert!(chip8.needs_redraw);
+        assert!(chip8.registers[0xF] == 0);
+        chip8.pc = 0x200;
+        chip8.cycle().unwrap();
+        for x in 0..8 {
+            for y in 0..5 {
+                assert!(chip8.display.pixels[64 * (12 + y) + (10 + x)] == 0);
+            }
+        }
+        assert!(chip8.needs_redraw);
+        assert!(chip8.registers[0xF] == 1);
+        chip8.registers[4] = 18;
+        chip8.pc = 0x200;
+        chip8.cycle().unwrap();
+        for x in 0..8 {
+            for y in 0..5 {
+                assert!(chip8.display.pixels[64 * (12 + y) + (18 + x)] == 1);
+            }
+        }
+        assert!(chip8.needs_redraw);
+        assert!(chip8.registers[0xF] == 0);
+    }
+
+    #[test]
+    fn quirk_shift_vx() {
+        let mut chip8 = Chip8::with_quirks(Quirks::superchip());
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x46;
+        chip8.registers[0x3] = 0x0D;
+        chip8.registers[0x4] = 0xF0;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x06);
+        assert!(chip8.registers[0xF] == 1);
+        chip8.pc = 0x200;
+        chip8.bus.bytes[0x201] = 0x4E;
+        chip8.registers[0x3] = 0x81;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x02);
+        assert!(chip8.registers[0xF] == 1);
+    }
+
+    #[test]
+    fn quirk_increment_i() {
+        let mut chip8 = Chip8::with_quirks(Quirks::cosmac_vip());
+        chip8.bus.bytes[0x200] = 0xF3;
+        chip8.bus.bytes[0x201] = 0x55;
+        chip8.bus.bytes[0x202] = 0xF3;
+        chip8.bus.bytes[0x203] = 0x65;
+        chip8.i = 0x400;
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x404);
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x408);
+
+        chip8.quirks = Quirks::superchip();
+        chip8.pc = 0x200;
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x408);
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x408);
+
+        chip8.quirks = Quirks::chip48();
+        chip8.pc = 0x200;
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x40B);
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x40E);
+    }
+
+    #[test]
+    fn quirks_by_name() {
+        assert!(Quirks::by_name("vip") == Some(Quirks::cosmac_vip()));
+        assert!(Quirks::by_name("chip48") == Some(Quirks::chip48()));
+        assert!(Quirks::by_name("schip") == Some(Quirks::superchip()));
+        assert!(Quirks::by_name("xochip") == Some(Quirks::xochip()));
+        assert!(Quirks::by_name("chip48") != Quirks::by_name("schip"));
+        assert!(Quirks::by_name("chip-8").is_none());
+    }
+
+    #[test]
+    fn quirk_jump_vx() {
+        let mut chip8 = Chip8::with_quirks(Quirks::superchip());
+        chip8.bus.bytes[0x200] = 0xB3;
+        chip8.bus.bytes[0x201] = 0x10;
+        chip8.registers[0] = 0x01;
+        chip8.registers[3] = 0x20;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x310 + 0x20);
+    }
+
+    #[test]
+    fn quirk_reset_vf() {
+        let mut chip8 = Chip8::with_quirks(Quirks::cosmac_vip());
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x43;
+        chip8.registers[0xF] = 0x42;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0xF] == 0);
+
+        chip8.quirks = Quirks::superchip();
+        chip8.pc = 0x200;
+        chip8.registers[0xF] = 0x42;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0xF] == 0x42);
+    }
+
+    #[test]
+    fn quirks_default() {
+        let mut chip8 = Chip8::new();
+        assert!(chip8.quirks == Quirks::default());
+        chip8.bus.bytes[0x200] = 0x83;
+        chip8.bus.bytes[0x201] = 0x46;
+        chip8.bus.bytes[0x202] = 0x83;
+        chip8.bus.bytes[0x203] = 0x41;
+        chip8.bus.bytes[0x204] = 0xF3;
+        chip8.bus.bytes[0x205] = 0x55;
+        chip8.registers[0x3] = 0x0D;
+        chip8.registers[0x4] = 0xF0;
+        chip8.i = 0x400;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0x3] == 0x78);
+        assert!(chip8.registers[0xF] == 0);
+        chip8.registers[0xF] = 0x42;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[0xF] == 0x42);
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0x400);
+    }
+
+    #[test]
+    fn quirk_wrap_sprites() {
+        let mut chip8 = Chip8::with_quirks(Quirks::xochip());
+        chip8.bus.bytes[0x200] = 0xD4;
+        chip8.bus.bytes[0x201] = 0x52;
+        chip8.registers[4] = 60;
+        chip8.registers[5] = 31;
+        chip8.i = 0x500;
+        chip8.bus.bytes[0x500] = 0xFF;
+        chip8.bus.bytes[0x501] = 0xFF;
+        chip8.cycle().unwrap();
+        for x in 0..8 {
+            assert!(chip8.display.pixels[64 * 31 + (60 + x) % 64] == 1);
+            assert!(chip8.display.pixels[(60 + x) % 64] == 1);
+        }
+
+        chip8.quirks = Quirks::cosmac_vip();
+        chip8.pc = 0x200;
+        chip8.cycle().unwrap();
+        for x in 0..8 {
+            assert!(chip8.display.pixels[64 * 31 + (60 + x) % 64] == (x >= 4) as u8);
+            assert!(chip8.display.pixels[(60 + x) % 64] == 1);
+        }
+
+        chip8.display.pixels = [0; 128 * 64];
+        chip8.registers[4] = 70;
+        chip8.registers[5] = 33;
+        chip8.pc = 0x200;
+        chip8.cycle().unwrap();
+        assert!(chip8.display.pixels.iter().all(|&pixel| pixel == 0));
+
+        chip8.quirks = Quirks::xochip();
+        chip8.pc = 0x200;
+        chip8.cycle().unwrap();
+        for x in 0..8 {
+            assert!(chip8.display.pixels[64 + 6 + x] == 1);
+            assert!(chip8.display.pixels[64 * 2 + 6 + x] == 1);
+        }
+    }
+
+    #[test]
+    fn op_dxy0() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0xFF;
+        chip8.bus.bytes[0x202] = 0xD4;
+        chip8.bus.bytes[0x203] = 0x50;
+        chip8.registers[4] = 100;
+        chip8.registers[5] = 40;
+        chip8.i = 0x500;
+        for i in 0..32 {
+            chip8.bus.bytes[chip8.i + i] = 0xFF;
+        }
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        for x in 0..16 {
+            for y in 0..16 {
+                assert!(chip8.display.pixels[128 * (40 + y) + (100 + x)] == 1);
+            }
+        }
+        assert!(chip8.registers[0xF] == 0);
+    }
+
+    #[test]
+    fn op_00fe_00ff() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0xFF;
+        chip8.bus.bytes[0x202] = 0x00;
+        chip8.bus.bytes[0x203] = 0xFE;
+        assert!((chip8.width(), chip8.height()) == (64, 32));
+        chip8.display.pixels[0] = 1;
+        chip8.cycle().unwrap();
+        assert!((chip8.width(), chip8.height()) == (128, 64));
+        assert!(chip8.display.pixels[0] == 0);
+        chip8.display.pixels[0] = 1;
+        chip8.cycle().unwrap();
+        assert!((chip8.width(), chip8.height()) == (64, 32));
+        assert!(chip8.display.pixels[0] == 0);
+    }
+
+    #[test]
+    fn op_00cn() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0xC3;
+        chip8.display.pixels[64 * 2 + 5] = 1;
+        chip8.display.pixels[64 * 31 + 5] = 1;
+        chip8.cycle().unwrap();
+        assert!(chip8.display.pixels[64 * 2 + 5] == 0);
+        assert!(chip8.display.pixels[64 * 5 + 5] == 1);
+        assert!(chip8.display.pixels.iter().filter(|&&p| p == 1).count() == 1);
+        assert!(chip8.needs_redraw);
+    }
+
+    #[test]
+    fn op_00fb_00fc() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0xFB;
+        chip8.bus.bytes[0x202] = 0x00;
+        chip8.bus.bytes[0x203] = 0xFC;
+        chip8.display.pixels[64 * 7 + 62] = 1;
+        chip8.display.pixels[64 * 7 + 10] = 1;
+        chip8.cycle().unwrap();
+        assert!(chip8.display.pixels[64 * 7 + 14] == 1);
+        assert!(chip8.display.pixels.iter().filter(|&&p| p == 1).count() == 1);
+        chip8.cycle().unwrap();
+        assert!(chip8.display.pixels[64 * 7 + 10] == 1);
+        assert!(chip8.display.pixels.iter().filter(|&&p| p == 1).count() == 1);
+    }
+
+    #[test]
+    fn op_00fd() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0xFD;
+        chip8.cycle().unwrap();
+        assert!(chip8.exited);
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x200);
+    }
+
+    #[test]
+    fn op_fx30() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF3;
+        chip8.bus.bytes[0x201] = 0x30;
+        chip8.registers[3] = 0x18;
+        chip8.cycle().unwrap();
+        for i in 0..10 {
+            assert!(chip8.bus.bytes[chip8.i + i] == BIG_FONTS[10 * 8 + i]);
+        }
+    }
+
+    #[test]
+    fn op_fx75_fx85() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF5;
+        chip8.bus.bytes[0x201] = 0x75;
+        chip8.bus.bytes[0x202] = 0xF5;
+        chip8.bus.bytes[0x203] = 0x85;
+        for i in 0..8 {
+            chip8.registers[i] = 10 + i as u8;
+        }
+        chip8.cycle().unwrap();
+        assert!(chip8.rpl[..6] == [10, 11, 12, 13, 14, 15]);
+        assert!(chip8.rpl[6] == 0);
+        for i in 0..8 {
+            chip8.registers[i] = 0;
+        }
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[..8] == [10, 11, 12, 13, 14, 15, 0, 0]);
+    }
+
+    #[test]
+    fn op_f000() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF0;
+        chip8.bus.bytes[0x201] = 0x00;
+        chip8.bus.bytes[0x202] = 0xBE;
+        chip8.bus.bytes[0x203] = 0xEF;
+        chip8.cycle().unwrap();
+        assert!(chip8.i == 0xBEEF);
+        assert!(chip8.pc == 0x204);
+
+        // Skips step over the whole 4 byte instruction.
+        chip8.pc = 0x1FE;
+        chip8.bus.bytes[0x1FE] = 0x30;
+        chip8.bus.bytes[0x1FF] = 0x00;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x204);
+    }
+
+    #[test]
+    fn op_5xy2_5xy3() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x52;
+        chip8.bus.bytes[0x201] = 0x42;
+        chip8.bus.bytes[0x202] = 0x54;
+        chip8.bus.bytes[0x203] = 0x23;
+        chip8.registers[2] = 0x22;
+        chip8.registers[3] = 0x33;
+        chip8.registers[4] = 0x44;
+        chip8.i = 0x600;
+        chip8.cycle().unwrap();
+        assert!(chip8.bus.bytes[0x600..0x603] == [0x22, 0x33, 0x44]);
+        assert!(chip8.i == 0x600);
+
+        // A reversed range loads in reverse order.
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[2..5] == [0x44, 0x33, 0x22]);
+        assert!(chip8.i == 0x600);
+    }
+
+    #[test]
+    fn op_fn01() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF3;
+        chip8.bus.bytes[0x201] = 0x01;
+        chip8.bus.bytes[0x202] = 0xD0;
+        chip8.bus.bytes[0x203] = 0x01;
+        chip8.bus.bytes[0x204] = 0xF2;
+        chip8.bus.bytes[0x205] = 0x01;
+        chip8.bus.bytes[0x206] = 0x00;
+        chip8.bus.bytes[0x207] = 0xE0;
+        chip8.i = 0x500;
+        chip8.bus.bytes[0x500] = 0xF0;
+        chip8.bus.bytes[0x501] = 0x3C;
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert!(chip8.display.pixels[..8] == [1, 1, 3, 3, 2, 2, 0, 0]);
+
+        // Clearing only affects the selected plane.
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert!(chip8.display.pixels[..8] == [1, 1, 1, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn op_f002_fx3a() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF0;
+        chip8.bus.bytes[0x201] = 0x02;
+        chip8.bus.bytes[0x202] = 0xF6;
+        chip8.bus.bytes[0x203] = 0x3A;
+        chip8.i = 0x500;
+        for i in 0..16 {
+            chip8.bus.bytes[0x500 + i] = i as u8;
+        }
+        chip8.registers[6] = 112;
+        assert!(chip8.audio_pattern.is_none());
+        chip8.cycle().unwrap();
+        assert!(chip8.audio_pattern.unwrap()[15] == 15);
+        chip8.cycle().unwrap();
+        assert!(chip8.pitch == 112);
+    }
+
+    #[test]
+    fn op_00dn() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0xD2;
+        chip8.display.pixels[64 * 5 + 5] = 3;
+        chip8.display.pixels[64 + 7] = 1;
+        chip8.cycle().unwrap();
+        assert!(chip8.display.pixels[64 * 3 + 5] == 1);
+        assert!(chip8.display.pixels[64 * 5 + 5] == 2);
+        assert!(chip8.display.pixels.iter().filter(|&&p| p != 0).count() == 2);
+    }
+
+    #[test]
+    fn op_fx15_fx07() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF3;
+        chip8.bus.bytes[0x201] = 0x15;
+        chip8.bus.bytes[0x202] = 0xF4;
+        chip8.bus.bytes[0x203] = 0x07;
+        chip8.registers[3] = 10;
+        chip8.cycle().unwrap();
+        assert!(chip8.delay_timer == 10);
+        for _ in 0..4 {
+            chip8.tick_timers();
+        }
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[4] == 6);
+    }
+
+    #[test]
+    fn op_fx18() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF3;
+        chip8.bus.bytes[0x201] = 0x18;
+        chip8.registers[3] = 2;
+        chip8.cycle().unwrap();
+        assert!(chip8.sound_timer == 2);
+        for _ in 0..3 {
+            chip8.tick_timers();
+        }
+        assert!(chip8.sound_timer == 0);
+    }
+
+    #[test]
+    fn run_frame() {
+        let mut chip8 = Chip8::new();
+        // Loop forever drawing a sprite and setting the delay timer once.
+        chip8.bus.bytes[0x200] = 0xF3;
+        chip8.bus.bytes[0x201] = 0x15;
+        chip8.bus.bytes[0x202] = 0xD0;
+        chip8.bus.bytes[0x203] = 0x01;
+        chip8.bus.bytes[0x204] = 0x60;
+        chip8.bus.bytes[0x205] = 0x00;
+        chip8.bus.bytes[0x206] = 0x12;
+        chip8.bus.bytes[0x207] = 0x04;
+        chip8.registers[3] = 5;
+        chip8.run_frame(10).unwrap();
+        assert!(chip8.delay_timer == 4);
+        assert!(chip8.needs_redraw);
+        chip8.run_frame(10).unwrap();
+        assert!(chip8.delay_timer == 3);
+        assert!(!chip8.needs_redraw);
+    }
+
+    #[test]
+    fn op_cxkk() {
+        let mut chip8 = Chip8::with_seed(5);
+        let mut random = Random::new(RandomSource::Xorshift, 5);
+        chip8.bus.bytes[0x200] = 0xC3;
+        chip8.bus.bytes[0x201] = 0xFF;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[3] == random.next_byte());
+        chip8.pc = 0x200;
+        chip8.bus.bytes[0x201] = 0x0F;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[3] == random.next_byte() & 0x0F);
+        chip8.pc = 0x200;
+        chip8.bus.bytes[0x201] = 0x00;
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[3] == 0);
+
+        // Loading a program starts the sequence over.
+        chip8.load(&[0xC3, 0xFF]).unwrap();
+        chip8.cycle().unwrap();
+        assert!(chip8.registers[3] == Chip8::with_seed(5).random.next_byte());
+    }
+
+    #[test]
+    fn subroutines() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x25;
+        chip8.bus.bytes[0x201] = 0x00;
+        chip8.bus.bytes[0x500] = 0x00;
+        chip8.bus.bytes[0x501] = 0xEE;
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x500);
+        assert!(chip8.sp == 1);
+        assert!(chip8.stack[0] == 0x200);
+        chip8.cycle().unwrap();
+        assert!(chip8.pc == 0x202);
+        assert!(chip8.sp == 0);
+    }
+
+    #[test]
+    fn op_unsupported() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0x00;
+        assert!(
+            chip8.cycle()
+                == Err(Chip8Error::UnknownOpcode {
+                    pc: 0x200,
+                    opcode: 0x0000,
+                })
+        );
+        assert!(chip8.pc == 0x200);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x22;
+        chip8.bus.bytes[0x201] = 0x00;
+        for _ in 0..chip8.stack.len() {
+            chip8.cycle().unwrap();
+        }
+        assert!(
+            chip8.cycle()
+                == Err(Chip8Error::StackOverflow {
+                    pc: 0x200,
+                    opcode: 0x2200,
+                })
+        );
+        assert!(chip8.sp == chip8.stack.len());
+        assert!(chip8.pc == 0x200);
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0x00;
+        chip8.bus.bytes[0x201] = 0xEE;
+        assert!(
+            chip8.cycle()
+                == Err(Chip8Error::StackUnderflow {
+                    pc: 0x200,
+                    opcode: 0x00EE,
+                })
+        );
+        assert!(chip8.sp == 0);
+        assert!(chip8.pc == 0x200);
+    }
+
+    #[test]
+    fn memory_out_of_bounds() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF3;
+        chip8.bus.bytes[0x201] = 0x55;
+        chip8.i = chip8.bus.bytes.len() - 2;
+        assert!(
+            chip8.cycle()
+                == Err(Chip8Error::MemoryOutOfBounds {
+                    pc: 0x200,
+                    opcode: 0xF355,
+                    address: chip8.bus.bytes.len(),
+                })
+        );
+        assert!(chip8.i == chip8.bus.bytes.len() - 2);
+        assert!(chip8.pc == 0x200);
+    }
+
+    #[test]
+    fn pc_out_of_bounds() {
+        let mut chip8 = Chip8::new();
+        chip8.pc = chip8.bus.bytes.len() - 1;
+        assert!(chip8.cycle() == Err(Chip8Error::PcOutOfBounds { pc: chip8.pc }));
+    }
+
+    #[test]
+    fn last_access() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load(&[0xF3, 0x55, 0xF1, 0x65, 0xD0, 0x15, 0x60, 0x01])
+            .unwrap();
+        chip8.i = 0x300;
+        chip8.cycle().unwrap();
+        assert!(
+            chip8.last_access()
+                == Some(Access {
+                    kind: AccessKind::Write,
+                    address: 0x300,
+                    len: 4,
+                })
+        );
+        chip8.cycle().unwrap();
+        assert!(chip8.last_access().map(|a| (a.kind, a.len)) == Some((AccessKind::Read, 2)));
+        chip8.cycle().unwrap();
+        assert!(chip8.last_access().map(|a| (a.kind, a.len)) == Some((AccessKind::Read, 5)));
+        chip8.cycle().unwrap();
+        assert!(chip8.last_access().is_none());
+    }
+}
@@ -0,0 +1,416 @@
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use state::fnv1a;
+use {Bus, Chip8, Chip8Error, Display, Increment, Quirks, Random, RandomSource};
+
+const HEADER: &str = "chip8-movie";
+const VERSION: u32 = 2;
+
+/// A key press or release, applied before the given cycle of the given frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub cycle: usize,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// A recording of all input to a program, to replay it exactly.
+///
+/// Besides the key events the movie holds everything else a run depends on: a hash of the
+/// program, the quirks, the source and seed of the random numbers and the number of cycles
+/// per frame. The hash of the final state verifies that a replay ended where the recording did.
+///
+/// Movies are stored as text, one field or event per line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub program: u64,
+    pub quirks: Quirks,
+    pub random: RandomSource,
+    pub seed: u64,
+    pub cycles_per_frame: usize,
+    pub frames: u64,
+    pub hash: u64,
+    pub events: Vec<KeyEvent>,
+}
+
+/// A movie that could not be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    /// The text does not start with the movie header.
+    BadHeader,
+    /// The movie was written by an incompatible version of the format.
+    UnsupportedVersion(u32),
+    /// The line with this number is malformed or out of order.
+    InvalidLine(usize),
+    /// A required field is missing.
+    Missing(&'static str),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MovieError::BadHeader => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "unsupported movie version {}", version)
+            }
+            MovieError::InvalidLine(line) => write!(f, "invalid movie line {}", line),
+            MovieError::Missing(field) => write!(f, "movie has no {}", field),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl Movie {
+    /// Whether the movie was recorded with this program.
+    pub fn matches(&self, program: &[u8]) -> bool {
+        fnv1a(program) == self.program
+    }
+
+    /// The random numbers the recording was made with, for the machine replaying it.
+    pub fn random(&self) -> Random {
+        Random::new(self.random, self.seed)
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, MovieError> {
+        let mut lines = text.lines().enumerate();
+        let header: Vec<&str> = match lines.next() {
+            Some((_, line)) => line.split_whitespace().collect(),
+            None => return Err(MovieError::BadHeader),
+        };
+        match header[..] {
+            [HEADER, version] => match version.parse() {
+                Ok(VERSION) => (),
+                Ok(version) => return Err(MovieError::UnsupportedVersion(version)),
+                Err(_) => return Err(MovieError::InvalidLine(1)),
+            },
+            _ => return Err(MovieError::BadHeader),
+        }
+
+        let (mut program, mut quirks, mut random, mut seed) = (None, None, None, None);
+        let (mut cycles_per_frame, mut frames, mut hash) = (None, None, None);
+        let mut events: Vec<KeyEvent> = vec![];
+        for (index, line) in lines {
+            let invalid = MovieError::InvalidLine(index + 1);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] => (),
+                ["program", value] => program = Some(hex(value).ok_or(invalid)?),
+                ["quirks", shift_vx, increment_i, jump_vx, reset_vf, wrap_sprites] => {
+                    let flag = |word: &str| match word {
+                        "0" => Ok(false),
+                        "1" => Ok(true),
+                        _ => Err(invalid),
+                    };
+                    quirks = Some(Quirks {
+                        shift_vx: flag(shift_vx)?,
+                        increment_i: increment_i
+                            .parse()
+                            .ok()
+                            .and_then(Increment::from_code)
+                            .ok_or(invalid)?,
+                        jump_vx: flag(jump_vx)?,
+                        reset_vf: flag(reset_vf)?,
+                        wrap_sprites: flag(wrap_sprites)?,
+                    });
+                }
+                ["random", name] => random = Some(RandomSource::by_name(name).ok_or(invalid)?),
+                ["seed", value] => seed = Some(value.parse().map_err(|_| invalid)?),
+                ["cycles", value] => cycles_per_frame = Some(value.parse().map_err(|_| invalid)?),
+                ["frames", value] => frames = Some(value.parse().map_err(|_| invalid)?),
+                ["hash", value] => hash = Some(hex(value).ok_or(invalid)?),
+                [kind @ "down", frame, cycle, key] | [kind @ "up", frame, cycle, key] => {
+                    let event = KeyEvent {
+                        frame: frame.parse().map_err(|_| invalid)?,
+                        cycle: cycle.parse().map_err(|_| invalid)?,
+                        key: u8::from_str_radix(key, 16).map_err(|_| invalid)?,
+                        pressed: kind == "down",
+                    };
+                    let ordered = events.last().map_or(true, |last| {
+                        (last.frame, last.cycle) <= (event.frame, event.cycle)
+                    });
+                    if event.key > 0xF || !ordered {
+                        return Err(invalid);
+                    }
+                    events.push(event);
+                }
+                _ => return Err(invalid),
+            }
+        }
+
+        Ok(Movie {
+            program: program.ok_or(MovieError::Missing("program"))?,
+            quirks: quirks.ok_or(MovieError::Missing("quirks"))?,
+            random: random.ok_or(MovieError::Missing("random"))?,
+            seed: seed.ok_or(MovieError::Missing("seed"))?,
+            cycles_per_frame: cycles_per_frame.ok_or(MovieError::Missing("cycles"))?,
+            frames: frames.ok_or(MovieError::Missing("frames"))?,
+            hash: hash.ok_or(MovieError::Missing("hash"))?,
+            events,
+        })
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let quirks = self.quirks;
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        writeln!(f, "program {:016x}", self.program)?;
+        writeln!(
+            f,
+            "quirks {} {} {} {} {}",
+            quirks.shift_vx as u8,
+            quirks.increment_i.code(),
+            quirks.jump_vx as u8,
+            quirks.reset_vf as u8,
+            quirks.wrap_sprites as u8
+        )?;
+        writeln!(f, "random {}", self.random.name())?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "cycles {}", self.cycles_per_frame)?;
+        writeln!(f, "frames {}", self.frames)?;
+        writeln!(f, "hash {:016x}", self.hash)?;
+        for event in &self.events {
+            let kind = if event.pressed { "down" } else { "up" };
+            writeln!(
+                f,
+                "{} {} {} {:X}",
+                kind, event.frame, event.cycle, event.key
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn hex(word: &str) -> Option<u64> {
+    u64::from_str_radix(word, 16).ok()
+}
+
+/// Records the key events of a run made with `Chip8::run_frame`, or cycle by cycle with
+/// `Recorder::cycle` to capture key events in the middle of a frame.
+///
+/// The recorder takes the quirks and random numbers from the machine, which must have just
+/// loaded the program.
+pub struct Recorder {
+    movie: Movie,
+    cycle: usize,
+}
+
+impl Recorder {
+    pub fn new<D: Display, B: Bus>(
+        program: &[u8],
+        chip8: &Chip8<D, B>,
+        cycles_per_frame: usize,
+    ) -> Self {
+        Recorder {
+            movie: Movie {
+                program: fnv1a(program),
+                quirks: chip8.quirks,
+                random: chip8.random.source(),
+                seed: chip8.random.seed(),
+                cycles_per_frame,
+                frames: 0,
+                hash: 0,
+                events: vec![],
+            },
+            cycle: 0,
+        }
+    }
+
+    /// Records a key press or release made before the next cycle.
+    pub fn key(&mut self, key: u8, pressed: bool) {
+        self.movie.events.push(KeyEvent {
+            frame: self.movie.frames,
+            cycle: self.cycle,
+            key,
+            pressed,
+        });
+    }
+
+    /// Runs one cycle of the frame, so that keys recorded after it apply from the next one.
+    pub fn cycle<D: Display, B: Bus>(&mut self, chip8: &mut Chip8<D, B>) -> Result<(), Chip8Error> {
+        chip8.cycle()?;
+        self.cycle += 1;
+        Ok(())
+    }
+
+    /// Records that a frame has run, either with `Chip8::run_frame` or as `cycles_per_frame`
+    /// calls to `cycle` followed by `Chip8::tick_timers`.
+    pub fn end_frame(&mut self) {
+        self.movie.frames += 1;
+        self.cycle = 0;
+    }
+
+    /// Finishes the movie with the hash of the final state of the machine.
+    pub fn finish<D: Display, B: Bus>(mut self, chip8: &Chip8<D, B>) -> Movie {
+        self.movie.hash = chip8.state_hash();
+        self.movie
+    }
+}
+
+/// Replays a movie on a machine set up with its quirks, random numbers and program.
+pub struct Player {
+    movie: Movie,
+    frame: u64,
+    next_event: usize,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Self {
+        Player {
+            movie,
+            frame: 0,
+            next_event: 0,
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// Runs the next frame of the movie, applying its key events at their cycles.
+    pub fn run_frame<D: Display, B: Bus>(
+        &mut self,
+        chip8: &mut Chip8<D, B>,
+    ) -> Result<(), Chip8Error> {
+        let mut redraw = false;
+        for cycle in 0..self.movie.cycles_per_frame {
+            while let Some(&event) = self.movie.events.get(self.next_event) {
+                if (event.frame, event.cycle) > (self.frame, cycle) {
+                    break;
+                }
+                if event.pressed {
+                    chip8.key_down(event.key);
+                } else {
+                    chip8.key_up(event.key);
+                }
+                self.next_event += 1;
+            }
+            chip8.cycle()?;
+            redraw |= chip8.needs_redraw;
+        }
+        chip8.needs_redraw = redraw;
+        chip8.tick_timers();
+        self.frame += 1;
+        Ok(())
+    }
+
+    /// Whether all frames of the movie have run.
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.frames
+    }
+
+    /// Whether the machine ended up in the state the recording did.
+    pub fn verify<D: Display, B: Bus>(&self, chip8: &Chip8<D, B>) -> bool {
+        chip8.state_hash() == self.movie.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn record(program: &[u8]) -> (Movie, u64) {
+        let quirks = Quirks::default();
+        let mut chip8 = Chip8::with_quirks(quirks);
+        chip8.random = Random::new(RandomSource::Xorshift, 42);
+        chip8.load(program).unwrap();
+        let mut recorder = Recorder::new(program, &chip8, 10);
+        for frame in 0..30 {
+            if frame % 7 == 3 {
+                recorder.key(5, true);
+                chip8.key_down(5);
+            }
+            if frame % 7 == 5 {
+                recorder.key(5, false);
+                chip8.key_up(5);
+            }
+            chip8.run_frame(10).unwrap();
+            recorder.end_frame();
+        }
+        let hash = chip8.state_hash();
+        (recorder.finish(&chip8), hash)
+    }
+
+    #[test]
+    fn replay() {
+        let program = include_bytes!("../../data/10print.ch8");
+        let (movie, hash) = record(program);
+        assert!(movie.frames == 30);
+        assert!(movie.events.len() == 8);
+        assert!(movie.hash == hash);
+
+        let movie = Movie::parse(&movie.to_string()).unwrap();
+        assert!(movie.matches(program));
+        assert!(!movie.matches(&program[1..]));
+        assert!(movie.random() == Random::new(RandomSource::Xorshift, 42));
+        let mut chip8 = Chip8::with_quirks(movie.quirks);
+        chip8.random = movie.random();
+        chip8.load(program).unwrap();
+        let mut player = Player::new(movie);
+        while !player.finished() {
+            player.run_frame(&mut chip8).unwrap();
+        }
+        assert!(player.verify(&chip8));
+
+        // A different seed draws different random numbers, so the replay diverges.
+        let (mut movie, _) = record(program);
+        movie.seed = 43;
+        let mut chip8 = Chip8::with_quirks(movie.quirks);
+        chip8.random = movie.random();
+        chip8.load(program).unwrap();
+        let mut player = Player::new(movie);
+        while !player.finished() {
+            player.run_frame(&mut chip8).unwrap();
+        }
+        assert!(!player.verify(&chip8));
+    }
+
+    #[test]
+    fn mid_frame_key() {
+        // V1 := 5; loop { V0 += 1; if key V1 is down, halt }
+        let program = [0x61, 0x05, 0x70, 0x01, 0xE1, 0x9E, 0x12, 0x02, 0x12, 0x08];
+        let mut chip8 = Chip8::new();
+        chip8.load(&program).unwrap();
+        let mut recorder = Recorder::new(&program, &chip8, 10);
+        for cycle in 0..10 {
+            if cycle == 4 {
+                recorder.key(5, true);
+                chip8.key_down(5);
+            }
+            recorder.cycle(&mut chip8).unwrap();
+        }
+        chip8.tick_timers();
+        recorder.end_frame();
+        chip8.run_frame(10).unwrap();
+        recorder.end_frame();
+        let movie = recorder.finish(&chip8);
+        assert!(movie.events[0].cycle == 4);
+        assert!(chip8.registers[0] == 2);
+
+        let mut chip8 = Chip8::new();
+        chip8.load(&program).unwrap();
+        let mut player = Player::new(movie);
+        while !player.finished() {
+            player.run_frame(&mut chip8).unwrap();
+        }
+        assert!(chip8.registers[0] == 2);
+        assert!(player.verify(&chip8));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let (movie, _) = record(&[0x12, 0x00]);
+        let text = movie.to_string();
+        assert!(Movie::parse("nope") == Err(MovieError::BadHeader));
+        assert!(Movie::parse("chip8-movie 1") == Err(MovieError::UnsupportedVersion(1)));
+        let truncated: Vec<&str> = text.lines().take(4).collect();
+        assert!(Movie::parse(&truncated.join("\n")) == Err(MovieError::Missing("seed")));
+        let unordered = format!("{}down 9 0 1\n", text);
+        assert!(Movie::parse(&unordered) == Err(MovieError::InvalidLine(17)));
+    }
+}
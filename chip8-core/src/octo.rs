@@ -0,0 +1,1022 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::prelude::v1::*;
+
+use Instruction;
+use Instruction::*;
+
+const ORIGIN: usize = 0x200;
+const MAX_EXPANSIONS: usize = 0x10000;
+
+/// A problem in Octo source, pointing at the line and column it was found at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OctoError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for OctoError {}
+
+/// Compiles [Octo](https://github.com/JohnEarnest/Octo) source into a program to be loaded at
+/// 0x200.
+///
+/// Supported are labels (`: name`), `:alias`, `:const`, `:calc`, `:macro` and `:byte`, the
+/// `loop`/`while`/`again` and `if`/`then`/`begin`/`else`/`end` control structures and the
+/// CHIP-8, SUPER-CHIP and XO-CHIP statements. Execution starts at the `main` label.
+///
+/// `:calc` expressions are evaluated right to left without precedence, like Octo does.
+pub fn compile(source: &str) -> Result<Vec<u8>, OctoError> {
+    let program = Compiler::new(source, true).run()?;
+    // Octo leaves out the jump to main when nothing comes before it.
+    if program.main == ORIGIN + 2 {
+        return Compiler::new(source, false)
+            .run()
+            .map(|program| program.rom);
+    }
+    Ok(program.rom)
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Register(u8),
+    Value(i64),
+}
+
+struct Condition {
+    lhs: u8,
+    op: Token,
+    rhs: Option<(Token, Operand)>,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+// An address that was used before its label was defined.
+struct Fixup {
+    token: Token,
+    offset: usize,
+    long: bool,
+}
+
+struct Program {
+    rom: Vec<u8>,
+    main: usize,
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    last: (usize, usize),
+    rom: Vec<u8>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    // The start of every open loop with the jumps of its `while`s.
+    loops: Vec<(Token, usize, Vec<usize>)>,
+    // The jump of every open `begin` or `else`.
+    branches: Vec<(Token, usize)>,
+    expansions: usize,
+}
+
+impl Compiler {
+    fn new(source: &str, preamble: bool) -> Self {
+        let mut compiler = Compiler {
+            tokens: tokenize(source),
+            last: (1, 1),
+            rom: vec![],
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: vec![],
+            loops: vec![],
+            branches: vec![],
+            expansions: 0,
+        };
+        if preamble {
+            let main = Token {
+                text: "main".to_string(),
+                line: 1,
+                column: 1,
+            };
+            compiler.jump(Jump(0), &main);
+        }
+        compiler
+    }
+
+    fn run(mut self) -> Result<Program, OctoError> {
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+        if let Some((token, _, _)) = self.loops.pop() {
+            return Err(error(&token, "loop without again"));
+        }
+        if let Some((token, _)) = self.branches.pop() {
+            return Err(error(&token, "begin without end"));
+        }
+        let main = match self.labels.get("main") {
+            Some(&main) => main,
+            None => return Err(self.end_error("this program is missing a 'main' label")),
+        };
+        for fixup in &self.fixups {
+            let address = match self.labels.get(&fixup.token.text) {
+                Some(&address) => address,
+                None => {
+                    let message = format!("undefined name '{}'", fixup.token.text);
+                    return Err(error(&fixup.token, message));
+                }
+            };
+            if fixup.long {
+                self.rom[fixup.offset + 2] = (address >> 8) as u8;
+                self.rom[fixup.offset + 3] = address as u8;
+            } else {
+                if address > 0xFFF {
+                    return Err(error(&fixup.token, "address does not fit in 12 bits"));
+                }
+                self.rom[fixup.offset] |= (address >> 8) as u8;
+                self.rom[fixup.offset + 1] = address as u8;
+            }
+        }
+        Ok(Program {
+            rom: self.rom,
+            main,
+        })
+    }
+
+    fn here(&self) -> usize {
+        ORIGIN + self.rom.len()
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.rom.extend_from_slice(&instruction.to_bytes());
+    }
+
+    fn end_error(&self, message: &str) -> OctoError {
+        OctoError {
+            line: self.last.0,
+            column: self.last.1,
+            message: message.to_string(),
+        }
+    }
+
+    fn next(&mut self) -> Result<Token, OctoError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.last = (token.line, token.column);
+                Ok(token)
+            }
+            None => Err(self.end_error("unexpected end of file")),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, OctoError> {
+        let token = self.next()?;
+        if token.text != text {
+            let message = format!("expected '{}', found '{}'", text, token.text);
+            return Err(error(&token, message));
+        }
+        Ok(token)
+    }
+
+    fn statement(&mut self) -> Result<(), OctoError> {
+        let token = self.next()?;
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.labels.insert(name.text, self.here());
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.constant(&value)?;
+                self.constants.insert(name.text, value);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let (token, value) = if self.peek() == Some("{") {
+                    (self.tokens[0].clone(), self.calc()?)
+                } else {
+                    let token = self.next()?;
+                    let value = self.constant(&token)?;
+                    (token, value)
+                };
+                let byte = byte(&token, value.floor() as i64)?;
+                self.rom.push(byte);
+            }
+            ";" | "return" => self.emit(Return),
+            "clear" => self.emit(Clear),
+            "exit" => self.emit(Exit),
+            "hires" => self.emit(HighRes),
+            "lores" => self.emit(LowRes),
+            "scroll-left" => self.emit(ScrollLeft),
+            "scroll-right" => self.emit(ScrollRight),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(ScrollDown(n));
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(ScrollUp(n));
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Plane(n));
+            }
+            "audio" => self.emit(Audio),
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Bcd(x));
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let save = token.text == "save";
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    self.emit(if save {
+                        SaveRange(x, y)
+                    } else {
+                        LoadRange(x, y)
+                    });
+                } else {
+                    self.emit(if save { Store(x) } else { Restore(x) });
+                }
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(StoreFlags(x));
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(RestoreFlags(x));
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Draw(x, y, n));
+            }
+            "jump" | "jump0" => {
+                let target = self.next()?;
+                let instruction = if token.text == "jump" {
+                    Jump(0)
+                } else {
+                    JumpOffset(0)
+                };
+                self.jump(instruction, &target);
+                self.resolve(&target)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token.text.as_str() {
+                    "delay" => SetDelay(x),
+                    "buzzer" => SetSound(x),
+                    _ => Pitch(x),
+                });
+            }
+            "i" => self.index()?,
+            "loop" => {
+                let here = self.here();
+                self.loops.push((token, here, vec![]));
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(error(&token, "while outside of a loop"));
+                }
+                let condition = self.condition()?;
+                self.skip(condition, true)?;
+                let offset = self.rom.len();
+                self.emit(Jump(0));
+                self.loops.last_mut().unwrap().2.push(offset);
+            }
+            "again" => {
+                let (_, start, whiles) = match self.loops.pop() {
+                    Some(found) => found,
+                    None => return Err(error(&token, "again without loop")),
+                };
+                self.emit(Jump(address(&token, start)?));
+                let end = self.here();
+                for offset in whiles {
+                    self.patch(&token, offset, end)?;
+                }
+            }
+            "if" => {
+                let condition = self.condition()?;
+                let then = self.next()?;
+                match then.text.as_str() {
+                    "then" => {
+                        self.skip(condition, false)?;
+                        self.statement()?;
+                    }
+                    "begin" => {
+                        self.skip(condition, true)?;
+                        let offset = self.rom.len();
+                        self.emit(Jump(0));
+                        self.branches.push((then, offset));
+                    }
+                    _ => {
+                        let message = format!("expected 'then' or 'begin', found '{}'", then.text);
+                        return Err(error(&then, message));
+                    }
+                }
+            }
+            "else" => {
+                let (_, offset) = match self.branches.pop() {
+                    Some(found) => found,
+                    None => return Err(error(&token, "else without begin")),
+                };
+                let jump = self.rom.len();
+                self.emit(Jump(0));
+                let here = self.here();
+                self.patch(&token, offset, here)?;
+                self.branches.push((token, jump));
+            }
+            "end" => {
+                let (_, offset) = match self.branches.pop() {
+                    Some(found) => found,
+                    None => return Err(error(&token, "end without begin")),
+                };
+                let here = self.here();
+                self.patch(&token, offset, here)?;
+            }
+            _ => self.other(token)?,
+        }
+        Ok(())
+    }
+
+    // Register assignments, bytes, macro invocations and subroutine calls.
+    fn other(&mut self, token: Token) -> Result<(), OctoError> {
+        if let Some(x) = self.register_named(&token.text) {
+            return self.assignment(x);
+        }
+        if let Some(value) = number(&token.text) {
+            let byte = byte(&token, value.floor() as i64)?;
+            self.rom.push(byte);
+            return Ok(());
+        }
+        if let Some(&value) = self.constants.get(&token.text) {
+            let byte = byte(&token, value.floor() as i64)?;
+            self.rom.push(byte);
+            return Ok(());
+        }
+        if self.macros.contains_key(&token.text) {
+            return self.expand(&token);
+        }
+        if token.text.starts_with(':') || !is_name(&token.text) {
+            let message = format!("unexpected '{}'", token.text);
+            return Err(error(&token, message));
+        }
+        self.jump(Call(0), &token);
+        self.resolve(&token)
+    }
+
+    fn assignment(&mut self, x: u8) -> Result<(), OctoError> {
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let instruction = match (op.text.as_str(), rhs.text.as_str()) {
+            (":=", "random") => {
+                let mask = self.next()?;
+                let mask = self.constant(&mask).and_then(|v| byte(&mask, v as i64))?;
+                Random(x, mask)
+            }
+            (":=", "delay") => LoadDelay(x),
+            (":=", "key") => WaitKey(x),
+            _ => match (op.text.as_str(), self.operand(&rhs)?) {
+                (":=", Operand::Register(y)) => Load(x, y),
+                (":=", Operand::Value(kk)) => LoadByte(x, byte(&rhs, kk)?),
+                ("+=", Operand::Register(y)) => Add(x, y),
+                ("+=", Operand::Value(kk)) => AddByte(x, byte(&rhs, kk)?),
+                ("-=", Operand::Register(y)) => Sub(x, y),
+                ("-=", Operand::Value(kk)) => AddByte(x, byte(&rhs, kk)?.wrapping_neg()),
+                ("=-", Operand::Register(y)) => SubReverse(x, y),
+                ("|=", Operand::Register(y)) => Or(x, y),
+                ("&=", Operand::Register(y)) => And(x, y),
+                ("^=", Operand::Register(y)) => Xor(x, y),
+                (">>=", Operand::Register(y)) => ShiftRight(x, y),
+                ("<<=", Operand::Register(y)) => ShiftLeft(x, y),
+                _ => {
+                    let message = format!("invalid operands for '{}'", op.text);
+                    return Err(error(&op, message));
+                }
+            },
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn index(&mut self) -> Result<(), OctoError> {
+        let op = self.next()?;
+        match op.text.as_str() {
+            ":=" => {
+                let target = self.next()?;
+                match target.text.as_str() {
+                    "hex" => {
+                        let x = self.register()?;
+                        self.emit(Font(x));
+                    }
+                    "bighex" => {
+                        let x = self.register()?;
+                        self.emit(BigFont(x));
+                    }
+                    "long" => {
+                        let target = self.next()?;
+                        match self.known_address(&target)? {
+                            Some(address) if (0..=0xFFFF).contains(&address) => {
+                                self.emit(LoadILong(address as u16))
+                            }
+                            Some(_) => {
+                                return Err(error(&target, "address does not fit in 16 bits"))
+                            }
+                            None => {
+                                self.fixups.push(Fixup {
+                                    token: target,
+                                    offset: self.rom.len(),
+                                    long: true,
+                                });
+                                self.emit(LoadILong(0));
+                            }
+                        }
+                    }
+                    _ => {
+                        self.jump(LoadI(0), &target);
+                        self.resolve(&target)?;
+                    }
+                }
+            }
+            "+=" => {
+                let x = self.register()?;
+                self.emit(AddI(x));
+            }
+            _ => {
+                let message = format!("invalid operands for '{}'", op.text);
+                return Err(error(&op, message));
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, OctoError> {
+        let lhs = self.register()?;
+        let op = self.next()?;
+        let rhs = match op.text.as_str() {
+            "key" | "-key" => None,
+            _ => {
+                let token = self.next()?;
+                let operand = self.operand(&token)?;
+                Some((token, operand))
+            }
+        };
+        Ok(Condition { lhs, op, rhs })
+    }
+
+    // Compiles a condition so that the next instruction is skipped when it equals `skip`.
+    fn skip(&mut self, condition: Condition, skip: bool) -> Result<(), OctoError> {
+        let Condition { lhs, op, rhs } = condition;
+        let (rhs_token, rhs) = match rhs {
+            Some(rhs) => rhs,
+            None => {
+                let pressed = op.text == "key";
+                self.emit(if pressed == skip {
+                    SkipKey(lhs)
+                } else {
+                    SkipNotKey(lhs)
+                });
+                return Ok(());
+            }
+        };
+        let instruction = match op.text.as_str() {
+            "==" | "!=" => {
+                let equal = (op.text == "==") == skip;
+                match rhs {
+                    Operand::Register(y) if equal => SkipEqual(lhs, y),
+                    Operand::Register(y) => SkipNotEqual(lhs, y),
+                    Operand::Value(kk) if equal => SkipEqualByte(lhs, byte(&rhs_token, kk)?),
+                    Operand::Value(kk) => SkipNotEqualByte(lhs, byte(&rhs_token, kk)?),
+                }
+            }
+            "<" | ">=" | ">" | "<=" => {
+                // VF = A - B leaves the flag set when A >= B.
+                let lhs = Operand::Register(lhs);
+                let (a, b) = match op.text.as_str() {
+                    "<" | ">=" => (lhs, rhs),
+                    _ => (rhs, lhs),
+                };
+                match (a, b) {
+                    (a, Operand::Register(b)) => {
+                        self.emit(match a {
+                            Operand::Register(a) => Load(0xF, a),
+                            Operand::Value(a) => LoadByte(0xF, byte(&rhs_token, a)?),
+                        });
+                        self.emit(Sub(0xF, b));
+                    }
+                    (Operand::Register(a), Operand::Value(b)) => {
+                        self.emit(LoadByte(0xF, byte(&rhs_token, b)?));
+                        self.emit(SubReverse(0xF, a));
+                    }
+                    _ => unreachable!(),
+                }
+                let flag = op.text == ">=" || op.text == "<=";
+                SkipEqualByte(0xF, (flag == skip) as u8)
+            }
+            _ => {
+                let message = format!("unknown comparison '{}'", op.text);
+                return Err(error(&op, message));
+            }
+        };
+        self.emit(instruction);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), OctoError> {
+        let name = self.name()?;
+        let mut args = vec![];
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            args.push(token.text);
+        }
+        let mut body = vec![];
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.text, Macro { args, body });
+        Ok(())
+    }
+
+    fn expand(&mut self, token: &Token) -> Result<(), OctoError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(error(token, "too many macro expansions"));
+        }
+        let count = self.macros[&token.text].args.len();
+        let mut values = vec![];
+        for _ in 0..count {
+            values.push(self.next()?);
+        }
+        let definition = &self.macros[&token.text];
+        let mut body = vec![];
+        for part in &definition.body {
+            match definition.args.iter().position(|arg| *arg == part.text) {
+                Some(i) => body.push(values[i].clone()),
+                None => body.push(part.clone()),
+            }
+        }
+        for part in body.into_iter().rev() {
+            self.tokens.push_front(part);
+        }
+        Ok(())
+    }
+
+    // Evaluates a `{ ... }` expression.
+    fn calc(&mut self) -> Result<f64, OctoError> {
+        let open = self.expect("{")?;
+        let mut tokens = vec![];
+        loop {
+            let token = self.next()?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+        let mut position = 0;
+        let value = self.expression(&open, &tokens, &mut position)?;
+        if let Some(token) = tokens.get(position) {
+            let message = format!("unexpected '{}'", token.text);
+            return Err(error(token, message));
+        }
+        Ok(value)
+    }
+
+    fn expression(
+        &self,
+        open: &Token,
+        tokens: &[Token],
+        position: &mut usize,
+    ) -> Result<f64, OctoError> {
+        let lhs = self.term(open, tokens, position)?;
+        let op = match tokens.get(*position) {
+            Some(op) if op.text != ")" => op,
+            _ => return Ok(lhs),
+        };
+        *position += 1;
+        let rhs = self.expression(open, tokens, position)?;
+        let value = match op.text.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (lhs as i64 & rhs as i64) as f64,
+            "|" => (lhs as i64 | rhs as i64) as f64,
+            "^" => (lhs as i64 ^ rhs as i64) as f64,
+            "<<" | ">>" => {
+                let shifted = if rhs < 0.0 || rhs > u32::MAX as f64 {
+                    None
+                } else if op.text == "<<" {
+                    (lhs as i64).checked_shl(rhs as u32)
+                } else {
+                    (lhs as i64).checked_shr(rhs as u32)
+                };
+                match shifted {
+                    Some(value) => value as f64,
+                    None => return Err(error(op, "shift out of range")),
+                }
+            }
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            _ => {
+                let message = format!("unknown operator '{}'", op.text);
+                return Err(error(op, message));
+            }
+        };
+        Ok(value)
+    }
+
+    fn term(&self, open: &Token, tokens: &[Token], position: &mut usize) -> Result<f64, OctoError> {
+        let token = match tokens.get(*position) {
+            Some(token) => token,
+            None => return Err(error(open, "incomplete expression")),
+        };
+        *position += 1;
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expression(open, tokens, position)?;
+                match tokens.get(*position) {
+                    Some(close) if close.text == ")" => *position += 1,
+                    _ => return Err(error(token, "missing ')'")),
+                }
+                Ok(value)
+            }
+            "-" => Ok(-self.term(open, tokens, position)?),
+            "~" => Ok(!(self.term(open, tokens, position)? as i64) as f64),
+            "HERE" => Ok(self.here() as f64),
+            _ => match self.labels.get(&token.text) {
+                Some(&address) => Ok(address as f64),
+                None => self.constant(token),
+            },
+        }
+    }
+
+    fn constant(&self, token: &Token) -> Result<f64, OctoError> {
+        if let Some(value) = number(&token.text) {
+            return Ok(value);
+        }
+        match self.constants.get(&token.text) {
+            Some(&value) => Ok(value),
+            None => {
+                let message = format!("undefined name '{}'", token.text);
+                Err(error(token, message))
+            }
+        }
+    }
+
+    fn operand(&self, token: &Token) -> Result<Operand, OctoError> {
+        match self.register_named(&token.text) {
+            Some(x) => Ok(Operand::Register(x)),
+            None => self
+                .constant(token)
+                .map(|v| Operand::Value(v.floor() as i64)),
+        }
+    }
+
+    fn name(&mut self) -> Result<Token, OctoError> {
+        let token = self.next()?;
+        if !is_name(&token.text) || self.register_named(&token.text).is_some() {
+            let message = format!("invalid name '{}'", token.text);
+            return Err(error(&token, message));
+        }
+        let defined = self.labels.contains_key(&token.text)
+            || self.constants.contains_key(&token.text)
+            || self.aliases.contains_key(&token.text)
+            || self.macros.contains_key(&token.text);
+        if defined {
+            let message = format!("the name '{}' is already defined", token.text);
+            return Err(error(&token, message));
+        }
+        Ok(token)
+    }
+
+    fn register_named(&self, name: &str) -> Option<u8> {
+        if let Some(&x) = self.aliases.get(name) {
+            return Some(x);
+        }
+        let mut chars = name.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v'), Some(x), None) | (Some('V'), Some(x), None) => {
+                x.to_digit(16).map(|x| x as u8)
+            }
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, OctoError> {
+        let token = self.next()?;
+        match self.register_named(&token.text) {
+            Some(x) => Ok(x),
+            None => {
+                let message = format!("expected a register, found '{}'", token.text);
+                Err(error(&token, message))
+            }
+        }
+    }
+
+    fn nibble(&mut self) -> Result<u8, OctoError> {
+        let token = self.next()?;
+        let value = self.constant(&token)?.floor() as i64;
+        if !(0..=0xF).contains(&value) {
+            return Err(error(&token, "value does not fit in 4 bits"));
+        }
+        Ok(value as u8)
+    }
+
+    // Resolves an address that is known now, if it is.
+    fn known_address(&self, token: &Token) -> Result<Option<i64>, OctoError> {
+        if let Some(&address) = self.labels.get(&token.text) {
+            return Ok(Some(address as i64));
+        }
+        if number(&token.text).is_some() || self.constants.contains_key(&token.text) {
+            return self.constant(token).map(|v| Some(v.floor() as i64));
+        }
+        if !is_name(&token.text) {
+            let message = format!("expected an address, found '{}'", token.text);
+            return Err(error(token, message));
+        }
+        Ok(None)
+    }
+
+    // Emits an instruction with a 12-bit address, to be filled in by `resolve`.
+    fn jump(&mut self, instruction: Instruction, target: &Token) {
+        self.fixups.push(Fixup {
+            token: target.clone(),
+            offset: self.rom.len(),
+            long: false,
+        });
+        self.emit(instruction);
+    }
+
+    // Fills in the address of the instruction just emitted by `jump` when it is known
+    // already, leaving forward references to be resolved at the end.
+    fn resolve(&mut self, target: &Token) -> Result<(), OctoError> {
+        if let Some(address) = self.known_address(target)? {
+            let fixup = self.fixups.pop().unwrap();
+            self.patch(target, fixup.offset, address as usize)?;
+        }
+        Ok(())
+    }
+
+    fn patch(&mut self, token: &Token, offset: usize, target: usize) -> Result<(), OctoError> {
+        let target = address(token, target)?;
+        self.rom[offset] = (self.rom[offset] & 0xF0) | (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+        Ok(())
+    }
+}
+
+fn error<S: Into<String>>(token: &Token, message: S) -> OctoError {
+    OctoError {
+        line: token.line,
+        column: token.column,
+        message: message.into(),
+    }
+}
+
+fn address(token: &Token, address: usize) -> Result<u16, OctoError> {
+    if address > 0xFFF {
+        return Err(error(token, "address does not fit in 12 bits"));
+    }
+    Ok(address as u16)
+}
+
+// Negative bytes are accepted as two's complement, e.g. `v0 := -1`.
+fn byte(token: &Token, value: i64) -> Result<u8, OctoError> {
+    if !(-0x80..=0xFF).contains(&value) {
+        return Err(error(token, "value does not fit in a byte"));
+    }
+    Ok(value as u8)
+}
+
+fn number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<f64>().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+// Splits source into whitespace separated tokens, dropping `#` comments. Braces and
+// parentheses are tokens of their own.
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut start = None;
+        for (i, c) in line.char_indices().chain(Some((line.len(), ' '))) {
+            let single = "{}()".contains(c);
+            if c.is_whitespace() || single {
+                if let Some(start) = start.take() {
+                    tokens.push_back(Token {
+                        text: line[start..i].to_string(),
+                        line: index + 1,
+                        column: start + 1,
+                    });
+                }
+                if single {
+                    tokens.push_back(Token {
+                        text: c.to_string(),
+                        line: index + 1,
+                        column: i + 1,
+                    });
+                }
+            } else if start.is_none() {
+                start = Some(i);
+            }
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Chip8;
+
+    fn run(source: &str, cycles: usize) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load(&compile(source).unwrap()).unwrap();
+        for _ in 0..cycles {
+            chip8.cycle().unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn statements() {
+        let program = compile(
+            "
+            : main
+                clear
+                v3 := 0x42   # load a byte
+                v3 += v4
+                i := long data
+                sprite v4 v5 5
+                save v1 - v2
+            : data
+                0xAA -1
+            ",
+        )
+        .unwrap();
+        assert!(
+            program
+                == vec![
+                    0x00, 0xE0, 0x63, 0x42, 0x83, 0x44, 0xF0, 0x00, 0x02, 0x0E, 0xD4, 0x55, 0x51,
+                    0x22, 0xAA, 0xFF,
+                ]
+        );
+    }
+
+    #[test]
+    fn main_jump() {
+        let program = compile(": sub ; : main sub").unwrap();
+        assert!(program == vec![0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]);
+    }
+
+    #[test]
+    fn directives() {
+        let program = compile(
+            "
+            :alias counter v5
+            :const SPEED 3
+            :calc DOUBLE { SPEED * 2 + 1 }
+            :calc RIGHT { 10 - 4 - 1 }
+            :macro add-twice reg amount { reg += amount reg += amount }
+            : main
+                counter := DOUBLE
+                add-twice counter SPEED
+                :byte RIGHT
+                :byte { HERE - 0x1FF }
+            ",
+        )
+        .unwrap();
+        assert!(program == vec![0x65, 0x09, 0x75, 0x03, 0x75, 0x03, 0x07, 0x08]);
+    }
+
+    #[test]
+    fn control_flow() {
+        let chip8 = run(
+            "
+            : main
+                v0 := 0
+                v1 := 0
+                loop
+                    v0 += 1
+                    if v0 < 5 then v1 += 1
+                    if v0 == 7 begin
+                        v2 := 1
+                    else
+                        v3 := 1
+                    end
+                    while v0 != 10
+                again
+                v4 := 1
+                if v0 >= 10 then v5 := 1
+                if v0 > v1 then v6 := 1
+                if v0 <= 9 then v7 := 1
+                if v8 key then v9 := 1
+                if v8 -key then va := 1
+            : halt
+                jump halt
+            ",
+            1000,
+        );
+        let registers = chip8.registers;
+        assert!(registers[0] == 10);
+        assert!(registers[1] == 4);
+        assert!(registers[2] == 1);
+        assert!(registers[3] == 1);
+        assert!(registers[4..8] == [1, 1, 1, 0]);
+        assert!(registers[9..11] == [0, 1]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| compile(source).unwrap_err();
+
+        let err = error(": main\n  v0 := 300");
+        assert!((err.line, err.column) == (2, 9));
+        assert!(err.message == "value does not fit in a byte");
+
+        let err = error(": main jump nowhere");
+        assert!(err.to_string() == "1:13: undefined name 'nowhere'");
+
+        assert!(error("clear").message == "this program is missing a 'main' label");
+        assert!(error(": main loop").message == "loop without again");
+        assert!(error(": main : main").message == "the name 'main' is already defined");
+        assert!(error(": main if v0 == 1 v1 := 2")
+            .message
+            .starts_with("expected 'then'"));
+
+        let err = error(":calc x { 1 << 70 }\n: main");
+        assert!((err.line, err.column) == (1, 13));
+        assert!(err.message == "shift out of range");
+        assert!(error(":calc x { 1 >> -1 }\n: main").message == "shift out of range");
+    }
+}
@@ -0,0 +1,123 @@
+/// Behaviour that differs between CHIP-8 interpreters.
+///
+/// Each flag selects the variant used by later platforms; the presets below bundle the
+/// combinations expected by ROMs written for each platform. The default is how `Chip8::new()`
+/// has always behaved and matches none of the presets: 8XY6/8XYE shift VY into VX, FX55/FX65
+/// leave I unchanged, BNNN jumps to 0xNNN + V0, 8XY1/8XY2/8XY3 keep VF, and DXYN clips
+/// sprites at the edges of the screen, including ones that start past them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VX in place instead of shifting VY into VX.
+    pub shift_vx: bool,
+    /// How far FX55/FX65 move I.
+    pub increment_i: Increment,
+    /// BNNN behaves as BXNN and jumps to 0xXNN + VX instead of 0xNNN + V0.
+    pub jump_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub reset_vf: bool,
+    /// DXYN wraps the starting coordinates and pixels past the edge of the screen instead of
+    /// clipping them.
+    pub wrap_sprites: bool,
+}
+
+/// How far FX55/FX65 move I after storing or loading V0 to VX.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Increment {
+    /// I is left unchanged.
+    #[default]
+    None,
+    /// I points past the last register (I = I + X + 1).
+    XPlusOne,
+    /// I points at the last register (I = I + X), as on CHIP-48.
+    X,
+}
+
+impl Increment {
+    /// The amount added to I after FX55/FX65.
+    pub fn amount(self, x: u8) -> usize {
+        match self {
+            Increment::None => 0,
+            Increment::XPlusOne => x as usize + 1,
+            Increment::X => x as usize,
+        }
+    }
+
+    /// The number used for this setting in save states and movies. `None` and `XPlusOne` keep
+    /// the 0 and 1 they had when the setting was a flag.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            Increment::None => 0,
+            Increment::XPlusOne => 1,
+            Increment::X => 2,
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Increment::None),
+            1 => Some(Increment::XPlusOne),
+            2 => Some(Increment::X),
+            _ => None,
+        }
+    }
+}
+
+impl Quirks {
+    /// The original RCA COSMAC VIP interpreter.
+    pub fn cosmac_vip() -> Self {
+        Quirks {
+            shift_vx: false,
+            increment_i: Increment::XPlusOne,
+            jump_vx: false,
+            reset_vf: true,
+            wrap_sprites: false,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators. It has no high resolution mode, so programs written
+    /// for it never switch to one.
+    pub fn chip48() -> Self {
+        Quirks {
+            shift_vx: true,
+            increment_i: Increment::X,
+            jump_vx: true,
+            reset_vf: false,
+            wrap_sprites: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP-48 calculators.
+    pub fn superchip() -> Self {
+        Quirks {
+            shift_vx: true,
+            increment_i: Increment::None,
+            jump_vx: true,
+            reset_vf: false,
+            wrap_sprites: false,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Self {
+        Quirks {
+            shift_vx: false,
+            increment_i: Increment::XPlusOne,
+            jump_vx: false,
+            reset_vf: false,
+            wrap_sprites: true,
+        }
+    }
+
+    /// Looks up a preset by its short name (`vip`, `chip48`, `schip` or `xochip`).
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "vip" => Some(Self::cosmac_vip()),
+            "chip48" => Some(Self::chip48()),
+            "schip" => Some(Self::superchip()),
+            "xochip" => Some(Self::xochip()),
+            _ => None,
+        }
+    }
+}
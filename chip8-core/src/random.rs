@@ -0,0 +1,117 @@
+/// The generators CXKK can draw its random bytes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomSource {
+    /// A xorshift64* generator: fast, with well distributed bytes.
+    Xorshift,
+}
+
+impl RandomSource {
+    /// Looks up a source by its short name (`xorshift`).
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "xorshift" => Some(RandomSource::Xorshift),
+            _ => None,
+        }
+    }
+
+    /// The short name accepted by `by_name`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RandomSource::Xorshift => "xorshift",
+        }
+    }
+}
+
+/// The random number generator owned by the machine.
+///
+/// The sequence only depends on the source and the seed, so a run can be reproduced exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Random {
+    pub(crate) source: RandomSource,
+    pub(crate) seed: u64,
+    pub(crate) state: u64,
+}
+
+impl Random {
+    pub fn new(source: RandomSource, seed: u64) -> Self {
+        let mut random = Random {
+            source,
+            seed,
+            state: 0,
+        };
+        random.reset();
+        random
+    }
+
+    pub fn source(&self) -> RandomSource {
+        self.source
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Starts the sequence over from the seed.
+    pub fn reset(&mut self) {
+        self.state = match self.source {
+            // xorshift gets stuck at zero, so the seed is scrambled into a nonzero state.
+            RandomSource::Xorshift => splitmix64(self.seed).max(1),
+        };
+    }
+
+    /// The next random byte.
+    pub fn next_byte(&mut self) -> u8 {
+        match self.source {
+            RandomSource::Xorshift => {
+                let mut x = self.state;
+                x ^= x >> 12;
+                x ^= x << 25;
+                x ^= x >> 27;
+                self.state = x;
+                (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            }
+        }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new(RandomSource::Xorshift, 0)
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let mut a = Random::new(RandomSource::Xorshift, 7);
+        let mut b = Random::new(RandomSource::Xorshift, 7);
+        let first = a.next_byte();
+        assert!(b.next_byte() == first);
+        assert!((0..100).all(|_| a.next_byte() == b.next_byte()));
+        a.reset();
+        assert!(a.next_byte() == first);
+        assert!(RandomSource::by_name("xorshift") == Some(RandomSource::Xorshift));
+        assert!(
+            RandomSource::by_name(RandomSource::Xorshift.name()) == Some(RandomSource::Xorshift)
+        );
+        assert!(RandomSource::by_name("dice").is_none());
+
+        // Every byte value shows up, roughly as often as the others.
+        let mut random = Random::new(RandomSource::Xorshift, 0);
+        let mut counts = [0; 256];
+        for _ in 0..256 * 100 {
+            counts[random.next_byte() as usize] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 50 && count < 150));
+    }
+}
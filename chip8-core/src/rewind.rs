@@ -0,0 +1,188 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use {Bus, Chip8, Display};
+
+/// A bounded history of machine states, one per frame, to run time backwards.
+///
+/// Only the newest state is kept in full. Every older state is stored as the difference to
+/// the state after it: the two serialized states XORed together, with the runs of zeros left
+/// by unchanged bytes compressed away. A frame usually changes a handful of bytes, so a
+/// frame of history costs a few bytes rather than a full save state.
+pub struct Rewind {
+    capacity: usize,
+    latest: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    /// Creates a history that remembers up to `capacity` frames back.
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            capacity,
+            latest: vec![],
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Records the state of the machine, dropping the oldest frame when full.
+    pub fn push<D: Display, B: Bus>(&mut self, chip8: &Chip8<D, B>) {
+        let state = chip8.save_state();
+        if !self.latest.is_empty() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            if self.capacity > 0 {
+                self.deltas.push_back(encode(&state, &self.latest));
+            }
+        }
+        self.latest = state;
+    }
+
+    /// Restores the machine to the frame before the last recorded one, returning false when
+    /// there is no earlier frame. Keys keep their current state.
+    pub fn rewind<D: Display, B: Bus>(&mut self, chip8: &mut Chip8<D, B>) -> bool {
+        let delta = match self.deltas.pop_back() {
+            Some(delta) => delta,
+            None => return false,
+        };
+        apply(&delta, &mut self.latest);
+        let keys = chip8.keys;
+        chip8
+            .load_state(&self.latest)
+            .expect("Rewind history holds an invalid state.");
+        chip8.keys = keys;
+        true
+    }
+
+    /// The number of frames that can be rewound.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Forgets all recorded frames.
+    pub fn clear(&mut self) {
+        self.latest.clear();
+        self.deltas.clear();
+    }
+
+    /// The number of bytes used by the history.
+    pub fn size(&self) -> usize {
+        self.latest.len() + self.deltas.iter().map(|delta| delta.len()).sum::<usize>()
+    }
+}
+
+// Encodes `a ^ b` as pairs of a run of zero bytes to skip and a run of literal bytes, with
+// both lengths as variable-length integers.
+fn encode(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < a.len() {
+        let start = i;
+        while i < a.len() && a[i] == b[i] {
+            i += 1;
+        }
+        write_length(&mut out, i - start);
+        let start = i;
+        while i < a.len() && a[i] != b[i] {
+            i += 1;
+        }
+        write_length(&mut out, i - start);
+        out.extend(a[start..i].iter().zip(&b[start..i]).map(|(x, y)| x ^ y));
+    }
+    out
+}
+
+fn apply(delta: &[u8], state: &mut [u8]) {
+    let mut position = 0;
+    let mut i = 0;
+    while i < delta.len() {
+        position += read_length(delta, &mut i);
+        let len = read_length(delta, &mut i);
+        for (byte, diff) in state[position..position + len]
+            .iter_mut()
+            .zip(&delta[i..i + len])
+        {
+            *byte ^= diff;
+        }
+        position += len;
+        i += len;
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 0x80 {
+        out.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+}
+
+fn read_length(data: &[u8], i: &mut usize) -> usize {
+    let mut len = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*i];
+        *i += 1;
+        len |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return len;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_round_trip() {
+        let a: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut b = a.clone();
+        b[0] ^= 1;
+        b[500..700].iter_mut().for_each(|x| *x = !*x);
+        b[999] = 0;
+        let delta = encode(&a, &b);
+        assert!(delta.len() < 220);
+        let mut state = b.clone();
+        apply(&delta, &mut state);
+        assert!(state == a);
+        apply(&delta, &mut state);
+        assert!(state == b);
+    }
+
+    #[test]
+    fn rewind() {
+        let mut chip8 = Chip8::new();
+        chip8
+            .load(include_bytes!("../../data/10print.ch8"))
+            .unwrap();
+        let mut history = Rewind::new(5);
+        assert!(!history.rewind(&mut chip8));
+
+        let mut states = vec![];
+        for _ in 0..8 {
+            chip8.run_frame(10).unwrap();
+            history.push(&chip8);
+            states.push(chip8.save_state());
+        }
+        assert!(history.len() == 5);
+        assert!(history.size() < 2 * states[0].len());
+
+        chip8.keys[3] = true;
+        for state in states[2..7].iter().rev() {
+            assert!(history.rewind(&mut chip8));
+            let mut restored = Chip8::new();
+            restored.load_state(state).unwrap();
+            restored.keys[3] = true;
+            assert!(chip8.save_state() == restored.save_state());
+        }
+        assert!(!history.rewind(&mut chip8));
+        assert!(history.is_empty());
+    }
+}
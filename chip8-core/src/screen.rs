@@ -0,0 +1,136 @@
+//! Exports of the visible framebuffer, as text or as a PNG image.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use {Bus, Chip8, Display};
+
+/// Colours for each combination of the two XO-CHIP bitplanes.
+pub const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [0, 255, 0], [0, 96, 255], [255, 255, 255]];
+
+/// Characters for each combination of the two XO-CHIP bitplanes in text exports.
+pub const CHARS: [char; 4] = ['.', '#', '+', '@'];
+
+/// The visible framebuffer as text, one line per row and one character per pixel.
+pub fn to_text<D: Display, B: Bus>(chip8: &Chip8<D, B>) -> String {
+    let (width, height) = chip8.display.size();
+    let mut text = String::with_capacity((width + 1) * height);
+    for y in 0..height {
+        text.extend((0..width).map(|x| CHARS[chip8.display.pixel(x, y) as usize & 3]));
+        text.push('\n');
+    }
+    text
+}
+
+/// The visible framebuffer as an RGB PNG image, one image pixel per machine pixel.
+pub fn to_png<D: Display, B: Bus>(chip8: &Chip8<D, B>) -> Vec<u8> {
+    let (width, height) = chip8.display.size();
+    // Every scanline starts with its filter type, which is always 0 (none).
+    let mut pixels = Vec::with_capacity((3 * width + 1) * height);
+    for y in 0..height {
+        pixels.push(0);
+        for x in 0..width {
+            pixels.extend_from_slice(&PALETTE[chip8.display.pixel(x, y) as usize & 3]);
+        }
+    }
+
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, default compression and filtering, no interlacing.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Wraps data in a zlib stream of uncompressed deflate blocks. The images are small enough
+// that compressing them is not worth a dependency.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text() {
+        let mut chip8 = Chip8::new();
+        chip8.display.set_pixel(0, 0, 1);
+        chip8.display.set_pixel(1, 1, 3);
+        let text = to_text(&chip8);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.len() == 32);
+        assert!(lines.iter().all(|line| line.len() == 64));
+        assert!(lines[0].starts_with("#."));
+        assert!(lines[1].starts_with(".@."));
+
+        chip8.display.resize(128, 64);
+        chip8.display.set_pixel(65, 0, 3);
+        let text = to_text(&chip8);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.len() == 64);
+        assert!(lines[0].len() == 128);
+        assert!(&lines[0][63..67] == "..@.");
+    }
+
+    #[test]
+    fn png() {
+        let chip8 = Chip8::new();
+        let png = to_png(&chip8);
+        assert!(png.starts_with(&[0x89, b'P', b'N', b'G']));
+        // IHDR with the image size, followed by the well-known CRC of an empty IEND chunk.
+        assert!(png[16..24] == [0, 0, 0, 64, 0, 0, 0, 32]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+        assert!(crc32(b"123456789") == 0xCBF4_3926);
+        assert!(adler32(b"Wikipedia") == 0x11E6_0398);
+    }
+}
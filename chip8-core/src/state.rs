@@ -0,0 +1,371 @@
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use {Bus, Chip8, Display, Increment, Quirks, Random, RandomSource, MEMORY_SIZE};
+
+const MAGIC: &[u8; 4] = b"CH8S";
+const VERSION: u16 = 2;
+
+/// A save state that could not be restored. The machine is left untouched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state signature.
+    BadMagic,
+    /// The save state was written by an incompatible version of the format.
+    UnsupportedVersion(u16),
+    /// The data ends before the save state does.
+    Truncated,
+    /// A field holds a value the machine can never be in.
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::InvalidValue(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+impl<D: Display, B: Bus> Chip8<D, B> {
+    /// Serializes the complete machine state.
+    ///
+    /// The format is versioned: a `CH8S` signature and a little endian `u16` version followed
+    /// by the fields in a fixed order. Version 2 added the random number generator; version 1
+    /// states are still accepted and leave the generator as it is.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MEMORY_SIZE + 128 * 64 + 512);
+        out.extend_from_slice(MAGIC);
+        write_u16(&mut out, VERSION);
+        for &quirk in &[
+            self.quirks.shift_vx as u8,
+            self.quirks.increment_i.code(),
+            self.quirks.jump_vx as u8,
+            self.quirks.reset_vf as u8,
+            self.quirks.wrap_sprites as u8,
+        ] {
+            out.push(quirk);
+        }
+        out.extend_from_slice(&self.registers);
+        write_u32(&mut out, self.i as u32);
+        write_u32(&mut out, self.pc as u32);
+        out.push(self.sp as u8);
+        for &address in self.stack.iter() {
+            write_u32(&mut out, address as u32);
+        }
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.extend((0..MEMORY_SIZE).map(|address| self.bus.peek(address)));
+        out.push(self.hires as u8);
+        out.push(self.planes);
+        // The screen is stored as the largest framebuffer, row after row at the current
+        // resolution.
+        let (width, height) = self.display.size();
+        for index in 0..128 * 64 {
+            out.push(if index < width * height {
+                self.display.pixel(index % width, index / width)
+            } else {
+                0
+            });
+        }
+        out.extend_from_slice(&self.rpl);
+        for &key in self.keys.iter() {
+            out.push(key as u8);
+        }
+        out.push(self.audio_pattern.is_some() as u8);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or([0; 16]));
+        out.push(self.pitch);
+        out.push(self.needs_input as u8);
+        out.push(self.input_register as u8);
+        out.push(self.exited as u8);
+        out.push(match self.random.source {
+            RandomSource::Xorshift => 0,
+        });
+        write_u64(&mut out, self.random.seed);
+        write_u64(&mut out, self.random.state);
+        out
+    }
+
+    /// Restores a state produced by `save_state`.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut reader = Reader { data: state };
+        if reader.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(StateError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != 1 && version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let mut chip8 = Chip8::with_quirks(Quirks {
+            shift_vx: reader.bool("quirk")?,
+            increment_i: Increment::from_code(reader.u8()?)
+                .ok_or(StateError::InvalidValue("quirk"))?,
+            jump_vx: reader.bool("quirk")?,
+            reset_vf: reader.bool("quirk")?,
+            wrap_sprites: reader.bool("quirk")?,
+        });
+        chip8.registers.copy_from_slice(reader.bytes(16)?);
+        chip8.i = reader.u32()? as usize;
+        chip8.pc = reader.u32()? as usize;
+        chip8.sp = reader.u8()? as usize;
+        if chip8.sp > chip8.stack.len() {
+            return Err(StateError::InvalidValue("stack pointer"));
+        }
+        for address in chip8.stack.iter_mut() {
+            *address = reader.u32()? as usize;
+        }
+        chip8.delay_timer = reader.u8()?;
+        chip8.sound_timer = reader.u8()?;
+        chip8.bus.bytes.copy_from_slice(reader.bytes(MEMORY_SIZE)?);
+        chip8.hires = reader.bool("resolution")?;
+        chip8.planes = reader.u8()?;
+        if chip8.planes > 3 {
+            return Err(StateError::InvalidValue("plane selection"));
+        }
+        chip8.display.resize(chip8.width(), chip8.height());
+        chip8
+            .display
+            .pixels
+            .copy_from_slice(reader.bytes(128 * 64)?);
+        if chip8.display.pixels.iter().any(|&pixel| pixel > 3) {
+            return Err(StateError::InvalidValue("pixel"));
+        }
+        chip8.rpl.copy_from_slice(reader.bytes(16)?);
+        for key in chip8.keys.iter_mut() {
+            *key = reader.bool("key")?;
+        }
+        let has_pattern = reader.bool("audio pattern")?;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(reader.bytes(16)?);
+        chip8.audio_pattern = if has_pattern { Some(pattern) } else { None };
+        chip8.pitch = reader.u8()?;
+        chip8.needs_input = reader.bool("input wait")?;
+        chip8.input_register = reader.u8()? as usize;
+        if chip8.input_register > 0xF {
+            return Err(StateError::InvalidValue("input register"));
+        }
+        chip8.exited = reader.bool("exit flag")?;
+        chip8.random = if version >= 2 {
+            let source = match reader.u8()? {
+                0 => RandomSource::Xorshift,
+                _ => return Err(StateError::InvalidValue("random source")),
+            };
+            Random {
+                source,
+                seed: reader.u64()?,
+                state: reader.u64()?,
+            }
+        } else {
+            self.random
+        };
+        chip8.needs_redraw = true;
+
+        self.restore(chip8);
+        Ok(())
+    }
+
+    // Takes over the state of a machine, redrawing its screen on this display.
+    fn restore(&mut self, chip8: Chip8) {
+        self.i = chip8.i;
+        self.pc = chip8.pc;
+        self.sp = chip8.sp;
+        self.delay_timer = chip8.delay_timer;
+        self.sound_timer = chip8.sound_timer;
+        self.stack = chip8.stack;
+        self.registers = chip8.registers;
+        self.hires = chip8.hires;
+        self.planes = chip8.planes;
+        self.rpl = chip8.rpl;
+        self.keys = chip8.keys;
+        self.audio_pattern = chip8.audio_pattern;
+        self.pitch = chip8.pitch;
+        self.needs_redraw = chip8.needs_redraw;
+        self.needs_input = chip8.needs_input;
+        self.exited = chip8.exited;
+        self.quirks = chip8.quirks;
+        self.input_register = chip8.input_register;
+        self.last_access = chip8.last_access;
+        self.random = chip8.random;
+
+        for (address, &byte) in chip8.bus.bytes.iter().enumerate() {
+            self.bus.poke(address, byte);
+        }
+        let (width, height) = chip8.display.size();
+        self.display.resize(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                self.display.set_pixel(x, y, chip8.display.pixel(x, y));
+            }
+        }
+    }
+
+    /// A 64-bit FNV-1a hash of the save state, to compare machines cheaply.
+    pub fn state_hash(&self) -> u64 {
+        fnv1a(&self.save_state())
+    }
+}
+
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self, field: &'static str) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue(field)),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut chip8 = Chip8::with_quirks(Quirks::xochip());
+        chip8.random = Random::new(RandomSource::Xorshift, 0x1234);
+        chip8.load(include_bytes!("../../data/logo.ch8")).unwrap();
+        for _ in 0..200 {
+            chip8.cycle().unwrap();
+        }
+        chip8.delay_timer = 12;
+        chip8.sound_timer = 34;
+        chip8.keys[7] = true;
+        chip8.audio_pattern = Some([0xAA; 16]);
+        let state = chip8.save_state();
+
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+        assert!(restored.save_state() == state);
+        assert!(restored.quirks == Quirks::xochip());
+        assert!(restored.pc == chip8.pc);
+        assert!(restored.i == chip8.i);
+        assert!(restored.registers == chip8.registers);
+        assert!(restored.display.pixels[..] == chip8.display.pixels[..]);
+        assert!(restored.delay_timer == 12);
+        assert!(restored.sound_timer == 34);
+        assert!(restored.keys[7]);
+        assert!(restored.random == chip8.random);
+        assert!(restored.random.seed() == 0x1234);
+
+        chip8.quirks = Quirks::chip48();
+        restored.load_state(&chip8.save_state()).unwrap();
+        assert!(restored.quirks == Quirks::chip48());
+    }
+
+    #[test]
+    fn version_1() {
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.registers[0] = 0x42;
+        let mut state = chip8.save_state();
+        // Version 1 ends before the random number generator.
+        state[4] = 1;
+        state.truncate(state.len() - 17);
+
+        let mut restored = Chip8::with_seed(9);
+        restored.load_state(&state).unwrap();
+        assert!(restored.registers[0] == 0x42);
+        assert!(restored.random.seed() == 9);
+    }
+
+    #[test]
+    fn input_wait() {
+        let mut chip8 = Chip8::new();
+        chip8.bus.bytes[0x200] = 0xF5;
+        chip8.bus.bytes[0x201] = 0x0A;
+        chip8.cycle().unwrap();
+        let state = chip8.save_state();
+
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+        assert!(restored.needs_input);
+        restored.key_down(0xB);
+        assert!(restored.registers[5] == 0xB);
+        assert!(restored.pc == 0x202);
+    }
+
+    #[test]
+    fn state_hash() {
+        let mut chip8 = Chip8::new();
+        let hash = chip8.state_hash();
+        assert!(Chip8::new().state_hash() == hash);
+        chip8.bus.bytes[0xFFFF] = 1;
+        assert!(chip8.state_hash() != hash);
+    }
+
+    #[test]
+    fn rejects_bad_states() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[0] = 0x42;
+        let state = chip8.save_state();
+
+        assert!(chip8.load_state(b"nope") == Err(StateError::BadMagic));
+        let mut future = state.clone();
+        future[4] = 0xFF;
+        assert!(chip8.load_state(&future) == Err(StateError::UnsupportedVersion(0xFF)));
+        assert!(chip8.load_state(&state[..state.len() - 1]) == Err(StateError::Truncated));
+        let mut invalid = state.clone();
+        invalid[6] = 2;
+        assert!(chip8.load_state(&invalid) == Err(StateError::InvalidValue("quirk")));
+        assert!(chip8.registers[0] == 0x42);
+    }
+}
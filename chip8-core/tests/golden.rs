@@ -0,0 +1,65 @@
+//! Runs every program in the workspace's `data/` for a fixed number of frames and compares
+//! the framebuffer with the golden image stored in `tests/golden/`.
+//!
+//! Set `UPDATE_GOLDENS=1` to write the current framebuffers as the new goldens instead.
+
+extern crate chip8_core;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chip8_core::{screen, Chip8};
+
+const FRAMES: usize = 120;
+const CYCLES_PER_FRAME: usize = 12;
+const SEED: u64 = 0;
+
+fn render(program: &[u8]) -> String {
+    let mut chip8 = Chip8::with_seed(SEED);
+    chip8.load(program).unwrap();
+    for _ in 0..FRAMES {
+        chip8.run_frame(CYCLES_PER_FRAME).expect("Program failed.");
+    }
+    screen::to_text(&chip8)
+}
+
+#[test]
+fn golden_framebuffers() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let update = env::var_os("UPDATE_GOLDENS").is_some();
+    let mut programs: Vec<_> = fs::read_dir(root.join("../data"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty());
+
+    let mut failures = vec![];
+    for path in programs {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let golden = root.join("tests/golden").join(format!("{}.txt", name));
+        let actual = render(&fs::read(&path).unwrap());
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!(
+                "{} differs from {}:\nexpected:\n{}actual:\n{}",
+                name,
+                golden.display(),
+                expected,
+                actual
+            )),
+            Err(_) => failures.push(format!("{} has no golden {}", name, golden.display())),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\nRun with UPDATE_GOLDENS=1 to accept the new framebuffers.",
+        failures.join("\n")
+    );
+}
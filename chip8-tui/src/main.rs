@@ -0,0 +1,289 @@
+use std::env;
+use std::fs;
+use std::io::{self, Stdout, Write};
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+extern crate chip8_core;
+use chip8_core::{octo, Chip8, Display, Quirks, Random, RandomSource, Rect, KEYPAD};
+
+#[macro_use]
+extern crate crossterm;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Colors, Print, ResetColor, SetColors};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+
+mod render;
+use render::{Cell, Mode};
+
+// Instructions per second, unless overridden with --speed or the program's speed file.
+const DEFAULT_SPEED: u32 = 700;
+const MIN_SPEED: u32 = 60;
+const MAX_SPEED: u32 = 1_000_000;
+const FRAMES_PER_SECOND: u32 = 60;
+
+// Frames a key stays down after a press when the terminal cannot report releases. Holding a
+// key down then relies on the terminal repeating it.
+const HOLD_FRAMES: u32 = 12;
+
+fn cycles_per_frame(speed: u32) -> usize {
+    ((speed + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND).max(1) as usize
+}
+
+// The speed remembered for a program, if its speed file holds one.
+fn read_speed(path: &str) -> Option<u32> {
+    let speed = fs::read_to_string(path).ok()?.trim().parse::<u32>().ok()?;
+    Some(speed.clamp(MIN_SPEED, MAX_SPEED))
+}
+
+// The keyboard rows the keypad is laid over, like in the windowed frontend.
+const KEYPAD_ROWS: [&str; 4] = ["1234", "qwer", "asdf", "zxcv"];
+
+fn keymap(key: char) -> Option<u8> {
+    let key = key.to_ascii_lowercase();
+    KEYPAD_ROWS
+        .iter()
+        .enumerate()
+        .find_map(|(row, keys)| keys.find(key).map(|column| KEYPAD[row][column]))
+}
+
+// Octo sources (`.8o`) are compiled on the fly.
+fn read_program(filename: &str) -> Vec<u8> {
+    if Path::new(filename).extension().and_then(|e| e.to_str()) == Some("8o") {
+        let source = fs::read_to_string(filename).expect("Unable to open program file.");
+        return octo::compile(&source).unwrap_or_else(|err| {
+            eprintln!("{}:{}", filename, err);
+            process::exit(1);
+        });
+    }
+    fs::read(filename).expect("Unable to open program file.")
+}
+
+// Puts the terminal in raw mode on the alternate screen, and back the way it was when dropped.
+struct Terminal {
+    out: Stdout,
+    releases: bool,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Self> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        // Only some terminals report key releases; the others get HOLD_FRAMES instead.
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            execute!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Terminal { out, releases })
+    }
+
+    // Draws an area of the screen cells, which start in the top left corner, changing colours
+    // only where needed.
+    fn draw_screen(&mut self, cells: &[Vec<Cell>], area: Rect) -> io::Result<()> {
+        let rgb = |[r, g, b]: [u8; 3]| Color::Rgb { r, g, b };
+        for (row, line) in cells.iter().enumerate().skip(area.y).take(area.height) {
+            queue!(self.out, MoveTo(area.x as u16, row as u16))?;
+            let mut colors = None;
+            for cell in line.iter().skip(area.x).take(area.width) {
+                if colors != Some((cell.fg, cell.bg)) {
+                    colors = Some((cell.fg, cell.bg));
+                    queue!(self.out, SetColors(Colors::new(rgb(cell.fg), rgb(cell.bg))))?;
+                }
+                queue!(self.out, Print(cell.ch))?;
+            }
+        }
+        queue!(self.out, ResetColor)
+    }
+
+    // Draws the lines of the panel that differ from the ones drawn before.
+    fn draw_panel(&mut self, column: u16, lines: &[String], before: &[String]) -> io::Result<()> {
+        for (row, line) in lines.iter().enumerate() {
+            if before.get(row) == Some(line) {
+                continue;
+            }
+            queue!(
+                self.out,
+                MoveTo(column, row as u16),
+                Print(format!("{:<16}", line))
+            )?;
+        }
+        self.out.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Runs frames until the program exits or fails, or Esc or Ctrl-C is pressed.
+fn run(
+    terminal: &mut Terminal,
+    chip8: &mut Chip8,
+    mode: Mode,
+    cycles_per_frame: usize,
+) -> Result<(), String> {
+    let mut held = [0; 16];
+    let mut size = None;
+    let mut panel = vec![];
+    let frame_interval = Duration::from_secs(1) / FRAMES_PER_SECOND;
+    let mut next_frame = Instant::now();
+    loop {
+        // Handle input until the next frame is due.
+        while event::poll(next_frame.saturating_duration_since(Instant::now()))
+            .map_err(|err| err.to_string())?
+        {
+            match event::read().map_err(|err| err.to_string())? {
+                Event::Key(key) => {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if key.code == KeyCode::Esc || ctrl_c {
+                        return Ok(());
+                    }
+                    let hex = match key.code {
+                        KeyCode::Char(c) => keymap(c),
+                        _ => None,
+                    };
+                    if let Some(hex) = hex {
+                        if key.kind == KeyEventKind::Release {
+                            chip8.key_up(hex);
+                            held[hex as usize] = 0;
+                        } else {
+                            chip8.key_down(hex);
+                            if !terminal.releases {
+                                held[hex as usize] = HOLD_FRAMES;
+                            }
+                        }
+                    }
+                }
+                Event::Resize(..) => size = None,
+                _ => (),
+            }
+        }
+
+        for (key, frames) in held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    chip8.key_up(key as u8);
+                }
+            }
+        }
+        chip8
+            .run_frame(cycles_per_frame)
+            .map_err(|err| err.to_string())?;
+        if chip8.exited {
+            return Ok(());
+        }
+
+        // Start from a blank terminal whenever the layout changes, and otherwise only draw
+        // what changed.
+        let (width, height) = chip8.display.size();
+        let mut dirty = chip8.display.take_dirty();
+        if size != Some((width, height)) {
+            size = Some((width, height));
+            execute!(terminal.out, Clear(ClearType::All)).map_err(|err| err.to_string())?;
+            dirty = Some(Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            });
+            panel.clear();
+        }
+        if let Some(dirty) = dirty {
+            terminal
+                .draw_screen(&render::screen(chip8, mode), mode.cells(dirty))
+                .map_err(|err| err.to_string())?;
+        }
+        let lines = render::panel(chip8);
+        let column = (width.div_ceil(mode.cell_size().0) + 2) as u16;
+        terminal
+            .draw_panel(column, &lines, &panel)
+            .map_err(|err| err.to_string())?;
+        panel = lines;
+
+        // Start over from now if we have fallen behind.
+        next_frame += frame_interval;
+        let now = Instant::now();
+        if next_frame < now {
+            next_frame = now;
+        }
+    }
+}
+
+fn main() {
+    let mut quirks = Quirks::default();
+    let mut speed = None;
+    let mut random = RandomSource::Xorshift;
+    let mut seed = None;
+    let mut mode = Mode::HalfBlocks;
+    let mut filename = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--quirks" {
+            let name = args.next().expect("Missing quirk profile name.");
+            quirks = Quirks::by_name(&name)
+                .expect("Unknown quirk profile (expected vip, chip48, schip or xochip).");
+        } else if arg == "--speed" {
+            let ips = args.next().expect("Missing instructions per second.");
+            let ips = ips
+                .parse::<u32>()
+                .expect("Speed must be a number of instructions per second.");
+            speed = Some(ips.clamp(MIN_SPEED, MAX_SPEED));
+        } else if arg == "--random" {
+            let name = args.next().expect("Missing random source name.");
+            random =
+                RandomSource::by_name(&name).expect("Unknown random source (expected xorshift).");
+        } else if arg == "--seed" {
+            let value = args.next().expect("Missing random seed.");
+            seed = Some(value.parse::<u64>().expect("Seed must be a number."));
+        } else if arg == "--braille" {
+            mode = Mode::Braille;
+        } else {
+            filename = Some(arg);
+        }
+    }
+
+    let speed_path = format!("{}.speed", filename.as_deref().unwrap_or("logo.ch8"));
+    let speed = speed
+        .or_else(|| read_speed(&speed_path))
+        .unwrap_or(DEFAULT_SPEED);
+    let program = match filename {
+        Some(filename) => read_program(&filename),
+        None => include_bytes!("../../data/logo.ch8").to_vec(),
+    };
+    let mut chip8 = Chip8::with_quirks(quirks);
+    // Without a seed the run still differs every time it starts.
+    let seed = seed.unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+        now.map(|time| time.as_nanos() as u64).unwrap_or(0)
+    });
+    chip8.random = Random::new(random, seed);
+    if let Err(err) = chip8.load(&program) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+
+    let mut terminal = Terminal::open().expect("Unable to set up the terminal.");
+    let result = run(&mut terminal, &mut chip8, mode, cycles_per_frame(speed));
+    drop(terminal);
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
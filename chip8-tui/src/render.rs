@@ -0,0 +1,191 @@
+use chip8_core::{screen, Bus, Chip8, Display, Rect};
+
+/// How pixels are packed into terminal cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// `▀` cells holding two pixels, one above the other, in full colour.
+    HalfBlocks,
+    /// Braille cells holding 2x4 pixels, in the colour of all planes lit in the cell.
+    Braille,
+}
+
+impl Mode {
+    /// The pixels covered by one cell, as columns and rows.
+    pub fn cell_size(&self) -> (usize, usize) {
+        match *self {
+            Mode::HalfBlocks => (1, 2),
+            Mode::Braille => (2, 4),
+        }
+    }
+
+    /// The cells covering an area of pixels.
+    pub fn cells(&self, pixels: Rect) -> Rect {
+        let (columns, rows) = self.cell_size();
+        let (x, y) = (pixels.x / columns, pixels.y / rows);
+        Rect {
+            x,
+            y,
+            width: (pixels.x + pixels.width).div_ceil(columns) - x,
+            height: (pixels.y + pixels.height).div_ceil(rows) - y,
+        }
+    }
+}
+
+/// One character on the terminal with its colours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: [u8; 3],
+    pub bg: [u8; 3],
+}
+
+// The bit of a braille character for each pixel of its cell, indexed by row and column.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// The visible framebuffer as rows of cells.
+pub fn screen<D: Display, B: Bus>(chip8: &Chip8<D, B>, mode: Mode) -> Vec<Vec<Cell>> {
+    let (width, height) = chip8.display.size();
+    let (columns, rows) = mode.cell_size();
+    let pixel = |x: usize, y: usize| {
+        if x < width && y < height {
+            chip8.display.pixel(x, y) as usize & 3
+        } else {
+            0
+        }
+    };
+    (0..height.div_ceil(rows))
+        .map(|row| {
+            (0..width.div_ceil(columns))
+                .map(|column| {
+                    let (x, y) = (column * columns, row * rows);
+                    match mode {
+                        Mode::HalfBlocks => Cell {
+                            ch: '▀',
+                            fg: screen::PALETTE[pixel(x, y)],
+                            bg: screen::PALETTE[pixel(x, y + 1)],
+                        },
+                        Mode::Braille => {
+                            let (mut dots, mut planes) = (0, 0);
+                            for (dy, row) in BRAILLE_DOTS.iter().enumerate() {
+                                for (dx, &dot) in row.iter().enumerate() {
+                                    let value = pixel(x + dx, y + dy);
+                                    if value != 0 {
+                                        dots |= dot;
+                                        planes |= value;
+                                    }
+                                }
+                            }
+                            Cell {
+                                ch: std::char::from_u32(0x2800 + dots).unwrap(),
+                                fg: screen::PALETTE[planes],
+                                bg: screen::PALETTE[0],
+                            }
+                        }
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The registers and timers, one short line each, for the panel beside the screen.
+pub fn panel<D: Display, B: Bus>(chip8: &Chip8<D, B>) -> Vec<String> {
+    let mut lines = vec![
+        format!("PC  0x{:03X}", chip8.pc),
+        format!("I   0x{:03X}", chip8.i),
+        format!("SP  {}", chip8.sp),
+        format!("DT  {}", chip8.delay_timer),
+        format!("ST  {}", chip8.sound_timer),
+        String::new(),
+    ];
+    for n in 0..8 {
+        lines.push(format!(
+            "V{:X}  {:02X}   V{:X}  {:02X}",
+            n,
+            chip8.registers[n],
+            n + 8,
+            chip8.registers[n + 8]
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_blocks() {
+        let mut chip8 = Chip8::new();
+        chip8.display.set_pixel(0, 0, 1);
+        chip8.display.set_pixel(1, 1, 3);
+        let cells = screen(&chip8, Mode::HalfBlocks);
+        assert!(cells.len() == 16 && cells[0].len() == 64);
+        let [black, green, _, white] = screen::PALETTE;
+        assert!(
+            cells[0][0]
+                == Cell {
+                    ch: '▀',
+                    fg: green,
+                    bg: black
+                }
+        );
+        assert!(
+            cells[0][1]
+                == Cell {
+                    ch: '▀',
+                    fg: black,
+                    bg: white
+                }
+        );
+        assert!(
+            cells[1][0]
+                == Cell {
+                    ch: '▀',
+                    fg: black,
+                    bg: black
+                }
+        );
+    }
+
+    #[test]
+    fn braille() {
+        let mut chip8 = Chip8::new();
+        chip8.hires = true;
+        chip8.display.resize(128, 64);
+        chip8.display.set_pixel(0, 0, 1);
+        chip8.display.set_pixel(1, 3, 2);
+        chip8.display.set_pixel(127, 63, 1);
+        let cells = screen(&chip8, Mode::Braille);
+        assert!(cells.len() == 16 && cells[0].len() == 64);
+        // Dots 1 and 8 are lit, and both planes show up in the colour.
+        assert!(cells[0][0].ch == '⢁');
+        assert!(cells[0][0].fg == screen::PALETTE[3]);
+        assert!(cells[15][63].ch == '⢀');
+        assert!(cells[15][63].fg == screen::PALETTE[1]);
+        assert!(cells[1][1].ch == '⠀');
+
+        let dirty = Rect {
+            x: 3,
+            y: 3,
+            width: 2,
+            height: 2,
+        };
+        let area = Mode::Braille.cells(dirty);
+        assert!((area.x, area.y, area.width, area.height) == (1, 0, 2, 2));
+        let area = Mode::HalfBlocks.cells(dirty);
+        assert!((area.x, area.y, area.width, area.height) == (3, 1, 2, 2));
+    }
+
+    #[test]
+    fn registers() {
+        let mut chip8 = Chip8::new();
+        chip8.registers[0x3] = 0x42;
+        chip8.registers[0xB] = 0x07;
+        chip8.delay_timer = 9;
+        let lines = panel(&chip8);
+        assert!(lines[0] == "PC  0x200");
+        assert!(lines[3] == "DT  9");
+        assert!(lines[9] == "V3  42   VB  07");
+    }
+}
@@ -1,8 +1,19 @@
+use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
 
-extern crate chip8;
-use chip8::Chip8;
+extern crate chip8_core;
+use chip8_core::{
+    asm, disasm, octo, screen, Audio, AudioSink, Chip8, Command as DebugCommand, Debugger, Display,
+    GdbServer, GdbState, Headless, Movie, Player, Quirks, Random, RandomSource, Recorder, Rewind,
+    Script, Until, KEYPAD,
+};
 
 extern crate glium;
 use glium::{glutin, Surface};
@@ -10,68 +21,433 @@ use glium::{glutin, Surface};
 extern crate rand;
 
 fn render(chip8: &Chip8, framebuffer: &mut [u8]) {
-    for x in 0..64 {
-        for y in 0..32 {
-            let ti = 3 * ((31 - y) * 64 + x);
-            let source = chip8.graphics[64 * y + x];
-            framebuffer[ti + 0] = 0 * source;
-            framebuffer[ti + 1] = 255 * source;
-            framebuffer[ti + 2] = 0 * source;
+    let (width, height) = chip8.display.size();
+    for x in 0..width {
+        for y in 0..height {
+            // Textures start at the bottom row.
+            let ti = 3 * ((height - 1 - y) * width + x);
+            let color = screen::PALETTE[chip8.display.pixel(x, y) as usize];
+            framebuffer[ti..ti + 3].copy_from_slice(&color);
         }
     }
 }
 
+// Instructions per second, unless overridden with --speed or the program's speed file.
+const DEFAULT_SPEED: u32 = 700;
+const MIN_SPEED: u32 = 60;
+const MAX_SPEED: u32 = 1_000_000;
+const FRAMES_PER_SECOND: u32 = 60;
+
+fn cycles_per_frame(speed: u32) -> usize {
+    ((speed + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND).max(1) as usize
+}
+
+// The speed remembered for a program, if its speed file holds one.
+fn read_speed(path: &str) -> Option<u32> {
+    let speed = fs::read_to_string(path).ok()?.trim().parse::<u32>().ok()?;
+    Some(speed.clamp(MIN_SPEED, MAX_SPEED))
+}
+
+// Frames a headless run lasts, unless overridden with --frames.
+const DEFAULT_HEADLESS_FRAMES: u64 = 600;
+
+// Seconds of gameplay that can be rewound with Backspace.
+const REWIND_SECONDS: u32 = 10;
+
+const SAMPLE_RATE: u32 = 44100;
+
+// Plays samples by piping them to ALSA's aplay, so the binary needs no audio libraries.
+struct AplaySink {
+    child: Child,
+}
+
+impl AplaySink {
+    fn spawn(sample_rate: u32) -> io::Result<Self> {
+        let child = Command::new("aplay")
+            .args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r"])
+            .arg(sample_rate.to_string())
+            .stdin(Stdio::piped())
+            .spawn()?;
+        Ok(AplaySink { child })
+    }
+}
+
+impl AudioSink for AplaySink {
+    fn write(&mut self, samples: &[f32]) {
+        let mut bytes = Vec::with_capacity(2 * samples.len());
+        for sample in samples {
+            let value = (sample * i16::MAX as f32) as i16;
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        if let Some(stdin) = self.child.stdin.as_mut() {
+            // Losing the audio device should not stop the emulator.
+            let _ = stdin.write_all(&bytes);
+        }
+    }
+}
+
+impl Drop for AplaySink {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+fn quick_save(chip8: &Chip8, path: &str) {
+    match fs::write(path, chip8.save_state()) {
+        Ok(()) => eprintln!("Saved state to {}", path),
+        Err(err) => eprintln!("Unable to save state to {}: {}", path, err),
+    }
+}
+
+fn save_speed(speed: u32, path: &str) {
+    eprintln!("Speed: {} instructions per second", speed);
+    if let Err(err) = fs::write(path, format!("{}\n", speed)) {
+        eprintln!("Unable to save speed to {}: {}", path, err);
+    }
+}
+
+fn quick_load(chip8: &mut Chip8, path: &str) {
+    match fs::read(path) {
+        Ok(state) => match chip8.load_state(&state) {
+            Ok(()) => eprintln!("Loaded state from {}", path),
+            Err(err) => eprintln!("Unable to load state from {}: {}", path, err),
+        },
+        Err(err) => eprintln!("Unable to load state from {}: {}", path, err),
+    }
+}
+
+// The scancodes of the 1, Q, A and Z keys, each starting a row of the keypad.
+const KEYPAD_ROWS: [u32; 4] = [2, 16, 30, 44];
+
 fn keymap(scancode: u32) -> Option<u8> {
-    match scancode {
-        2 => Some(0x1),
-        3 => Some(0x2),
-        4 => Some(0x3),
-        5 => Some(0xC),
-        16 => Some(0x4),
-        17 => Some(0x5),
-        18 => Some(0x6),
-        19 => Some(0xD),
-        30 => Some(0x7),
-        31 => Some(0x8),
-        32 => Some(0x9),
-        33 => Some(0xE),
-        44 => Some(0xA),
-        45 => Some(0x0),
-        46 => Some(0xB),
-        47 => Some(0xF),
-        _ => None,
+    KEYPAD_ROWS
+        .iter()
+        .position(|&start| scancode >= start && scancode < start + 4)
+        .map(|row| KEYPAD[row][(scancode - KEYPAD_ROWS[row]) as usize])
+}
+
+fn save_movie(recorder: Recorder, chip8: &Chip8, path: &str) {
+    let movie = recorder.finish(chip8);
+    match fs::write(path, movie.to_string()) {
+        Ok(()) => eprintln!("Recorded {} frames to {}", movie.frames, path),
+        Err(err) => eprintln!("Unable to save movie to {}: {}", path, err),
     }
 }
 
-fn main() {
-    let mut rng = rand::thread_rng();
-    let mut chip8 = Chip8::new();
-
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() == 2 {
-        let filename = &args[1];
-        let mut f = File::open(filename).expect("Unable to open program file.");
-        let mut program: Vec<u8> = vec![];
-        f.read_to_end(&mut program)
-            .expect("Error reading program file.");
-        chip8.load(&program);
+fn read_movie(path: &str) -> Movie {
+    let text = fs::read_to_string(path).expect("Unable to open movie file.");
+    Movie::parse(&text).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        std::process::exit(1);
+    })
+}
+
+// Reports whether a replay ended in the recorded state, and exits accordingly.
+fn finish_replay(player: &Player, chip8: &Chip8) -> ! {
+    if player.verify(chip8) {
+        eprintln!("Replay matches the recording.");
+        std::process::exit(0);
     } else {
-        let logo = include_bytes!("../data/logo.ch8");
-        chip8.load(logo);
+        eprintln!(
+            "Replay diverged: final state hash {:016x}, recorded {:016x}.",
+            chip8.state_hash(),
+            player.movie().hash
+        );
+        std::process::exit(1);
     }
+}
+
+// Octo sources (`.8o`) are compiled on the fly.
+fn read_program(filename: &str) -> Vec<u8> {
+    if Path::new(filename).extension().and_then(|e| e.to_str()) == Some("8o") {
+        let source = fs::read_to_string(filename).expect("Unable to open program file.");
+        return octo::compile(&source).unwrap_or_else(|err| {
+            eprintln!("{}:{}", filename, err);
+            std::process::exit(1);
+        });
+    }
+    let mut f = File::open(filename).expect("Unable to open program file.");
+    let mut program: Vec<u8> = vec![];
+    f.read_to_end(&mut program)
+        .expect("Error reading program file.");
+    program
+}
+
+// Prints a full listing of a program: `chip8 disasm program.ch8`.
+fn disasm_command(args: &[String]) {
+    let filename = args.first().expect("Missing program file.");
+    let program = read_program(filename);
+    for line in disasm::listing(&program, 0x200) {
+        println!("{}", line);
+    }
+}
+
+// Assembles a source file into a program: `chip8 asm source.asm [program.ch8]`.
+fn asm_command(args: &[String]) {
+    let source = args.first().expect("Missing source file.");
+    let output = match args.get(1) {
+        Some(output) => output.clone(),
+        None => Path::new(source)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    match asm::assemble_file(source) {
+        Ok(program) => fs::write(&output, program).expect("Unable to write program file."),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Runs the program without a window, then prints the frames run and the state hash and
+// dumps the framebuffer: as a PNG image for `.png` files, as text otherwise or for `-`.
+fn run_headless(chip8: &mut Chip8, headless: &Headless, dump: Option<&str>) {
+    let result = headless.run(chip8);
+    if let Err(err) = result {
+        eprintln!("{}", err);
+    }
+    match dump {
+        Some("-") => print!("{}", screen::to_text(chip8)),
+        Some(path) => {
+            let data = if path.ends_with(".png") {
+                screen::to_png(chip8)
+            } else {
+                screen::to_text(chip8).into_bytes()
+            };
+            fs::write(path, data).expect("Unable to write framebuffer dump.");
+        }
+        None => (),
+    }
+    match result {
+        Ok(summary) => {
+            println!("frames {}", summary.frames);
+            println!("hash {:016x}", chip8.state_hash());
+            if headless.until.is_some() && !summary.condition_met {
+                std::process::exit(2);
+            }
+        }
+        Err(_) => std::process::exit(1),
+    }
+}
+
+// Reads debugger commands on a thread of its own, so the window stays responsive.
+fn spawn_prompt() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    io::stdout().flush().unwrap();
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|a| a.as_str()) {
+        Some("disasm") => return disasm_command(&args[1..]),
+        Some("asm") => return asm_command(&args[1..]),
+        _ => (),
+    }
+
+    let mut quirks = Quirks::default();
+    let mut speed = None;
+    let mut mute = false;
+    let mut protect = false;
+    let mut debug = false;
+    let mut gdb_port = None;
+    let mut random = RandomSource::Xorshift;
+    let mut seed = None;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut headless = false;
+    let mut frames = DEFAULT_HEADLESS_FRAMES;
+    let mut until = None;
+    let mut script = Script::default();
+    let mut dump = None;
+    let mut filename = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--quirks" {
+            let name = args.next().expect("Missing quirk profile name.");
+            quirks = Quirks::by_name(&name)
+                .expect("Unknown quirk profile (expected vip, chip48, schip or xochip).");
+        } else if arg == "--speed" {
+            let ips = args.next().expect("Missing instructions per second.");
+            let ips = ips
+                .parse::<u32>()
+                .expect("Speed must be a number of instructions per second.");
+            speed = Some(ips.clamp(MIN_SPEED, MAX_SPEED));
+        } else if arg == "--mute" {
+            mute = true;
+        } else if arg == "--protect" {
+            protect = true;
+        } else if arg == "--debug" {
+            debug = true;
+        } else if arg == "--gdb" {
+            let port = args.next().expect("Missing GDB port.");
+            gdb_port = Some(port.parse::<u16>().expect("GDB port must be a number."));
+        } else if arg == "--random" {
+            let name = args.next().expect("Missing random source name.");
+            random =
+                RandomSource::by_name(&name).expect("Unknown random source (expected xorshift).");
+        } else if arg == "--seed" {
+            let value = args.next().expect("Missing random seed.");
+            seed = Some(value.parse::<u64>().expect("Seed must be a number."));
+        } else if arg == "--record" {
+            record_path = Some(args.next().expect("Missing movie file."));
+        } else if arg == "--replay" {
+            replay_path = Some(args.next().expect("Missing movie file."));
+        } else if arg == "--headless" {
+            headless = true;
+        } else if arg == "--frames" {
+            let value = args.next().expect("Missing number of frames.");
+            frames = value.parse::<u64>().expect("Frames must be a number.");
+        } else if arg == "--until" {
+            let value = args.next().expect("Missing condition.");
+            until = Some(
+                Until::parse(&value)
+                    .expect("Unknown condition (expected pc=ADDRESS, idle or exit)."),
+            );
+        } else if arg == "--input" {
+            let path = args.next().expect("Missing input script.");
+            let text = fs::read_to_string(&path).expect("Unable to open input script.");
+            script = Script::parse(&text).unwrap_or_else(|err| {
+                eprintln!("{}: {}", path, err);
+                std::process::exit(1);
+            });
+        } else if arg == "--dump" {
+            dump = Some(args.next().expect("Missing framebuffer dump file."));
+        } else {
+            filename = Some(arg);
+        }
+    }
+
+    let state_path = format!("{}.state", filename.as_deref().unwrap_or("logo.ch8"));
+    let speed_path = format!("{}.speed", filename.as_deref().unwrap_or("logo.ch8"));
+    let mut speed = speed
+        .or_else(|| read_speed(&speed_path))
+        .unwrap_or(DEFAULT_SPEED);
+    let program = match filename {
+        Some(filename) => read_program(&filename),
+        None => include_bytes!("../data/logo.ch8").to_vec(),
+    };
+
+    if headless {
+        let mut chip8 = Chip8::with_quirks(quirks);
+        chip8.random = Random::new(random, seed.unwrap_or(0));
+        if protect {
+            chip8.bus.protect_interpreter();
+        }
+        if let Err(err) = chip8.load(&program) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        let headless = Headless {
+            cycles_per_frame: cycles_per_frame(speed),
+            frames,
+            until,
+            script,
+        };
+        return run_headless(&mut chip8, &headless, dump.as_deref());
+    }
+
+    // Movies must see exactly the frames that were recorded, which a debugger would interrupt.
+    if (record_path.is_some() || replay_path.is_some()) && (debug || gdb_port.is_some()) {
+        eprintln!("Movies cannot be recorded or replayed under a debugger.");
+        std::process::exit(1);
+    }
+    let mut player = replay_path.map(|path| {
+        let movie = read_movie(&path);
+        if !movie.matches(&program) {
+            eprintln!("{} was recorded with a different program.", path);
+            std::process::exit(1);
+        }
+        quirks = movie.quirks;
+        Player::new(movie)
+    });
+
+    let mut chip8 = Chip8::with_quirks(quirks);
+    chip8.random = match player {
+        Some(ref player) => player.movie().random(),
+        None => Random::new(random, seed.unwrap_or_else(rand::random)),
+    };
+    if protect {
+        chip8.bus.protect_interpreter();
+    }
+    if let Err(err) = chip8.load(&program) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let mut recorder = record_path
+        .as_ref()
+        .map(|_| Recorder::new(&program, &chip8, cycles_per_frame(speed)));
+    // Anything that changes the machine outside of its input would break the movie.
+    let movie = recorder.is_some() || player.is_some();
 
     let mut events_loop = glutin::EventsLoop::new();
     let window = glutin::WindowBuilder::new();
     let context = glutin::ContextBuilder::new();
     let display = glium::Display::new(window, context, &events_loop).unwrap();
 
+    let mut audio = Audio::new(SAMPLE_RATE);
+    let mut sink = if mute {
+        None
+    } else {
+        match AplaySink::spawn(SAMPLE_RATE) {
+            Ok(sink) => Some(sink),
+            Err(err) => {
+                eprintln!("Sound disabled, unable to start aplay: {}", err);
+                None
+            }
+        }
+    };
+
+    let mut debugger = Debugger::new(chip8);
+    let commands = if debug {
+        println!(
+            "Paused at 0x{:03X}, type 'help' for commands.",
+            debugger.chip8.pc
+        );
+        println!("{}", debugger.list(debugger.chip8.pc, 0, 1));
+        prompt();
+        Some(spawn_prompt())
+    } else {
+        None
+    };
+    let mut gdb = gdb_port.map(|port| {
+        eprintln!("Waiting for GDB to connect to localhost:{}", port);
+        GdbServer::listen(port).expect("Unable to accept GDB connection.")
+    });
+    let mut paused = debug || gdb.is_some();
+
+    let frame_interval = Duration::from_secs(1) / FRAMES_PER_SECOND;
+    let mut next_frame = Instant::now();
+
+    let mut history = Rewind::new((REWIND_SECONDS * FRAMES_PER_SECOND) as usize);
+    history.push(&debugger.chip8);
+    let mut rewinding = false;
+
     let mut closed = false;
     while !closed {
-        if chip8.needs_redraw {
-            let mut framebuffer: Vec<u8> = vec![0; 3 * chip8.graphics.len()];
-            render(&chip8, &mut framebuffer);
+        if debugger.chip8.needs_redraw {
+            let (width, height) = debugger.chip8.display.size();
+            let mut framebuffer: Vec<u8> = vec![0; 3 * width * height];
+            render(&debugger.chip8, &mut framebuffer);
 
-            let image = glium::texture::RawImage2d::from_raw_rgb(framebuffer, (64, 32));
+            let image = glium::texture::RawImage2d::from_raw_rgb(
+                framebuffer,
+                (width as u32, height as u32),
+            );
             let texture = glium::Texture2d::new(&display, image).unwrap();
             let surface = texture.as_surface();
 
@@ -80,25 +456,161 @@ fn main() {
             target.finish().unwrap();
         }
 
-        events_loop.poll_events(|ev| match ev {
-            glutin::Event::WindowEvent { event, .. } => match event {
-                glutin::WindowEvent::Closed => closed = true,
-                glutin::WindowEvent::KeyboardInput { input, .. } => match keymap(input.scancode) {
-                    Some(keycode) => match input.state {
-                        glium::glutin::ElementState::Pressed => {
-                            chip8.key_down(keycode);
+        events_loop.poll_events(|ev| {
+            if let glutin::Event::WindowEvent { event, .. } = ev {
+                match event {
+                    glutin::WindowEvent::Closed => closed = true,
+                    glutin::WindowEvent::KeyboardInput { input, .. } => {
+                        let pressed = input.state == glutin::ElementState::Pressed;
+                        match input.virtual_keycode {
+                            Some(glutin::VirtualKeyCode::Equals)
+                            | Some(glutin::VirtualKeyCode::Add)
+                                if pressed && !movie =>
+                            {
+                                speed = (speed * 5 / 4).min(MAX_SPEED);
+                                save_speed(speed, &speed_path);
+                            }
+                            Some(glutin::VirtualKeyCode::Minus)
+                            | Some(glutin::VirtualKeyCode::Subtract)
+                                if pressed && !movie =>
+                            {
+                                speed = (speed * 4 / 5).max(MIN_SPEED);
+                                save_speed(speed, &speed_path);
+                            }
+                            Some(glutin::VirtualKeyCode::F5) if pressed => {
+                                quick_save(&debugger.chip8, &state_path);
+                            }
+                            Some(glutin::VirtualKeyCode::F9) if pressed && !movie => {
+                                quick_load(&mut debugger.chip8, &state_path);
+                            }
+                            Some(glutin::VirtualKeyCode::Back) if !movie => rewinding = pressed,
+                            _ => (),
                         }
-                        glium::glutin::ElementState::Released => {
-                            chip8.key_up(keycode);
+                        if let Some(keycode) = keymap(input.scancode).filter(|_| player.is_none()) {
+                            if let Some(ref mut recorder) = recorder {
+                                recorder.key(keycode, pressed);
+                            }
+                            match input.state {
+                                glium::glutin::ElementState::Pressed => {
+                                    debugger.chip8.key_down(keycode);
+                                }
+                                glium::glutin::ElementState::Released => {
+                                    debugger.chip8.key_up(keycode);
+                                }
+                            }
                         }
-                    },
-                    None => (),
-                },
-                _ => (),
-            },
-            _ => (),
+                    }
+                    _ => (),
+                }
+            }
         });
 
-        chip8.cycle(&mut rng);
+        if let Some(ref commands) = commands {
+            while let Ok(line) = commands.try_recv() {
+                match DebugCommand::parse(&line) {
+                    Ok(DebugCommand::Continue) => paused = false,
+                    Ok(DebugCommand::Quit) => closed = true,
+                    Ok(command) => println!("{}", debugger.execute(command)),
+                    Err(err) => println!("{}", err),
+                }
+                if paused {
+                    prompt();
+                }
+            }
+        }
+
+        if let Some(mut server) = gdb.take() {
+            match server.poll(&mut debugger).map(|()| server.state()) {
+                Ok(GdbState::Stopped) => {
+                    paused = true;
+                    gdb = Some(server);
+                }
+                Ok(GdbState::Running) => {
+                    paused = false;
+                    gdb = Some(server);
+                }
+                Ok(GdbState::Detached) => paused = false,
+                Ok(GdbState::Killed) => closed = true,
+                Err(err) => {
+                    eprintln!("GDB connection lost: {}", err);
+                    paused = false;
+                }
+            }
+        }
+
+        if let Some(ref mut player) = player {
+            if player.finished() {
+                finish_replay(player, &debugger.chip8);
+            }
+            if let Err(err) = player.run_frame(&mut debugger.chip8) {
+                // A recording ends with the error that stopped it.
+                eprintln!("{}", err);
+                finish_replay(player, &debugger.chip8);
+            }
+            if let Some(ref mut sink) = sink {
+                audio.render_frame(&debugger.chip8, sink);
+            }
+        } else if rewinding && !paused {
+            history.rewind(&mut debugger.chip8);
+        } else if !paused {
+            match debugger.run_frame(cycles_per_frame(speed)) {
+                Ok(None) => {
+                    history.push(&debugger.chip8);
+                    if let Some(ref mut recorder) = recorder {
+                        recorder.end_frame();
+                    }
+                }
+                Ok(Some(stop)) => {
+                    paused = true;
+                    if let Some(ref mut server) = gdb {
+                        if let Err(err) = server.stopped(stop) {
+                            eprintln!("GDB connection lost: {}", err);
+                        }
+                    }
+                    if debug {
+                        println!("\n{}", stop);
+                        println!("{}", debugger.list(debugger.chip8.pc, 0, 1));
+                        prompt();
+                    }
+                }
+                // Under a debugger an error pauses execution to allow inspecting the machine.
+                Err(err) if debug || gdb.is_some() => {
+                    paused = true;
+                    if let Some(ref mut server) = gdb {
+                        if let Err(err) = server.faulted(err) {
+                            eprintln!("GDB connection lost: {}", err);
+                        }
+                    }
+                    if debug {
+                        println!("\n{}", err);
+                        prompt();
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    if let (Some(mut recorder), Some(path)) = (recorder.take(), record_path) {
+                        recorder.end_frame();
+                        save_movie(recorder, &debugger.chip8, &path);
+                    }
+                    std::process::exit(1);
+                }
+            }
+            if let Some(ref mut sink) = sink {
+                audio.render_frame(&debugger.chip8, sink);
+            }
+        }
+
+        // Sleep until the next frame is due, or start over from now if we have fallen behind.
+        next_frame += frame_interval;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+
+    if let (Some(recorder), Some(path)) = (recorder, record_path) {
+        save_movie(recorder, &debugger.chip8, &path);
     }
 }